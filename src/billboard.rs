@@ -0,0 +1,56 @@
+//! Players drawn as upright, camera-facing cards showing their portrait.
+//! Cards only turn about Y, so they stay standing through `CameraSpin`s, and
+//! they are ordinary alpha-masked meshes in the 3D scene, so walls in front
+//! hide them like anything else.
+use bevy::prelude::*;
+use crate::camera::IsoCamera;
+use crate::roster::{Avatar, Roster};
+
+/// A card that turns to face the `IsoCamera`.
+#[derive(Component, Debug)]
+pub struct Billboard;
+
+/// The card showing a player's portrait (a child of the player).
+#[derive(Component, Debug)]
+pub struct PortraitCard;
+
+/// Turns every billboard toward the camera about Y only, and stretches it
+/// so that, seen from the camera's pitch, it looks as tall as it was made.
+pub fn face_iso_camera(
+    cam_q: Query<&IsoCamera>,
+    mut q: Query<&mut Transform, With<Billboard>>,
+) {
+    let Ok(iso) = cam_q.single() else { return };
+    // Orthographic: every card faces the same way, toward the camera's side
+    // of its orbit (see `camera::iso_camera_transform_at`).
+    let yaw = iso.yaw_deg.to_radians();
+    let rotation = Quat::from_rotation_y(yaw.cos().atan2(yaw.sin()));
+    let stretch = 1.0 / iso.pitch_deg.to_radians().cos();
+    for mut t in &mut q {
+        t.rotation = rotation;
+        t.scale.y = stretch;
+    }
+}
+
+/// Players whose avatar or card may have just appeared.
+type PortraitChanged = Or<(Changed<Avatar>, Changed<Children>)>;
+
+/// Puts each player's portrait on its card once both exist (remote players
+/// learn their `Avatar` after they spawn) and whenever the avatar changes.
+pub fn show_portraits(
+    roster: Option<Res<Roster>>,
+    players: Query<(&Avatar, &Children), PortraitChanged>,
+    cards: Query<&MeshMaterial3d<StandardMaterial>, With<PortraitCard>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let Some(roster) = roster else { return };
+    for (avatar, children) in &players {
+        let Some(portrait) = roster.portrait(avatar) else { continue };
+        for card in cards.iter_many(children) {
+            if let Some(material) = materials.get_mut(&card.0) {
+                material.base_color = Color::WHITE;
+                material.base_color_texture = Some(portrait.image.clone());
+            }
+        }
+    }
+}
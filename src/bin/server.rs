@@ -0,0 +1,36 @@
+//! Dedicated server: the simulation with no window, renderer, camera or minimap.
+//!
+//! ```text
+//! cargo run --bin server -- [--port 5000] [--level levels/sample.tmj] [--sim-latency 80 ...]
+//! cargo run -- --connect 127.0.0.1:5000
+//! ```
+use bevy::{
+    prelude::*,
+    app::ScheduleRunnerPlugin,
+    log::LogPlugin,
+    state::app::StatesPlugin,
+};
+use bevy_renet::renet::RenetServer;
+use isometric::{constants, net, states::AppState};
+use std::time::Duration;
+
+fn main() -> AppExit {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+        1.0 / constants::TICK_HZ,
+    ))))
+    .add_plugins((LogPlugin::default(), AssetPlugin::default(), StatesPlugin))
+    .insert_resource(net::NetMode::Host { port: net::port_arg() })
+    // No menu here: straight to loading the level, then in game.
+    .insert_state(AppState::Loading)
+    .add_systems(OnEnter(AppState::Menu), quit_on_failure)
+    .add_systems(Update, quit_on_failure.run_if(in_state(AppState::InGame).and(not(resource_exists::<RenetServer>))));
+    isometric::add_simulation(&mut app);
+    app.run()
+}
+
+/// The level failed to load (`level::wait_for_level` went back to the menu),
+/// or the socket couldn't be opened (`net::server::start_server` logged why).
+fn quit_on_failure(mut exit: MessageWriter<AppExit>) {
+    exit.write(AppExit::error());
+}
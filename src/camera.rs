@@ -1,5 +1,11 @@
-use bevy::{prelude::*, input::keyboard::KeyCode};
+use bevy::{
+    prelude::*,
+    camera::ScalingMode,
+    input::{keyboard::KeyCode, mouse::{AccumulatedMouseScroll, MouseScrollUnit}},
+};
+use crate::collision::MoveIntent;
 use crate::constants;
+use crate::level;
 use crate::world;
 
 /* ---------------- Camera state ---------------- */
@@ -9,12 +15,18 @@ pub struct IsoCamera {
     pub yaw_deg: f32,   // current yaw
     pub pitch_deg: f32, // ~35.264 for iso
     pub radius: f32,    // distance from target
+    pub zoom: f32,      // current orthographic viewport height (world units)
+    pub zoom_target: f32,
+    pub zoom_min: f32,  // closest zoom (smallest viewport height)
+    pub zoom_max: f32,  // farthest zoom
 }
 
 #[derive(Component)]
 pub struct MinimapCamera {
     pub height: f32, // how high above the world to render from                       
-    pub center: Vec3 // what point to look at (usually Vec3::ZERO or player)
+    pub center: Vec3, // what point to look at (usually Vec3::ZERO or player)
+    pub viewport_height: f32, // used as-is when `zoom_ratio` is None
+    pub zoom_ratio: Option<f32>, // Some(r): viewport height = r × IsoCamera::zoom
 }
 
 
@@ -28,11 +40,42 @@ pub struct CameraSpin {
     pub queued_steps: i32, // additional ±90° steps waiting
 }
 
+/// Spring-damped follow of the player. `focus` is the point the iso camera
+/// orbits; spins only change yaw, so they orbit the smoothed focus.
 #[derive(Component)]
 pub struct CameraFollow { 
     pub stiffness: f32, 
     pub damping: f32, 
-    pub vel: Vec3 
+    pub vel: Vec3,
+    pub focus: Vec3,       // smoothed look-at point
+    pub dead_zone: Vec2,   // half-size (screen right, screen up) on the ground; no follow inside it
+    pub look_ahead: f32,   // how far ahead of a moving player to aim, in world units
+    pub ahead: Vec3,       // current look-ahead offset, eased toward `look_ahead` along the motion
+}
+
+/// How fast the look-ahead offset eases in and out, per second.
+const LOOK_AHEAD_EASE: f32 = 4.0;
+
+impl CameraFollow {
+    /// Critically damped (fastest settle without overshoot) for the given stiffness.
+    pub fn critically_damped(stiffness: f32) -> Self {
+        Self {
+            stiffness,
+            damping: 2.0 * stiffness.sqrt(),
+            vel: Vec3::ZERO,
+            focus: Vec3::ZERO,
+            dead_zone: Vec2::new(0.5, 0.5),
+            look_ahead: 1.5,
+            ahead: Vec3::ZERO,
+        }
+    }
+
+    /// Jumps straight to `target`, dropping any motion.
+    pub fn snap_to(&mut self, target: Vec3) {
+        self.focus = target;
+        self.ahead = Vec3::ZERO;
+        self.vel = Vec3::ZERO;
+    }
 }
 
 /* ---------------- Input: queue spins ---------------- */
@@ -41,56 +84,76 @@ pub fn handle_spin_input(
     keys: Res<ButtonInput<KeyCode>>,
     mut q: Query<(&IsoCamera, &mut CameraSpin)>,
 ) {
-    let Ok((iso, mut spin)) = q.single_mut() else { panic!()};
-
     let mut steps: i32 = 0;
     if keys.just_pressed(KeyCode::KeyQ) { steps -= 1; }
     if keys.just_pressed(KeyCode::KeyE) { steps += 1; }
     if steps == 0 { return; }
 
-    // If idle, start immediately; else queue.
-    let spinning = spin.t < spin.duration;
-    if !spinning {
-        let step = steps.signum(); // take one step now
-        spin.start_yaw = iso.yaw_deg;
-        spin.end_yaw = iso.yaw_deg + 90.0 * step as f32;
-        spin.t = 0.0;
-        spin.queued_steps += steps - step;
-    } else {
-        spin.queued_steps += steps;
+    for (iso, mut spin) in &mut q {
+        // If idle, start immediately; else queue.
+        let spinning = spin.t < spin.duration;
+        if !spinning {
+            let step = steps.signum(); // take one step now
+            spin.start_yaw = iso.yaw_deg;
+            spin.end_yaw = iso.yaw_deg + 90.0 * step as f32;
+            spin.t = 0.0;
+            spin.queued_steps += steps - step;
+        } else {
+            spin.queued_steps += steps;
+        }
+    }
+}
+
+/* ---------------- Input: zoom ---------------- */
+
+/// Mouse wheel steps / +,- keys held per second, as a zoom factor.
+const ZOOM_STEP: f32 = 1.15;
+const ZOOM_KEY_RATE: f32 = 2.0;
+
+pub fn handle_zoom_input(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    scroll: Res<AccumulatedMouseScroll>,
+    mut q: Query<&mut IsoCamera>,
+) {
+    let lines = match scroll.unit {
+        MouseScrollUnit::Line => scroll.delta.y,
+        MouseScrollUnit::Pixel => scroll.delta.y / 40.0, // roughly one wheel notch
+    };
+    let mut factor = ZOOM_STEP.powf(-lines);
+
+    let zoom_in = keys.any_pressed([KeyCode::Equal, KeyCode::NumpadAdd]);
+    let zoom_out = keys.any_pressed([KeyCode::Minus, KeyCode::NumpadSubtract]);
+    if zoom_in { factor /= ZOOM_KEY_RATE.powf(time.delta_secs()); }
+    if zoom_out { factor *= ZOOM_KEY_RATE.powf(time.delta_secs()); }
+
+    if factor == 1.0 { return; }
+    for mut iso in &mut q {
+        iso.zoom_target = (iso.zoom_target * factor).clamp(iso.zoom_min, iso.zoom_max);
     }
 }
 
 /* ---------------- Animation ---------------- */
 
+/// Advances the yaw only; `follow_center_smooth` places the camera around its focus.
 pub fn animate_camera_spin(
     time: Res<Time>,
-    player_q: Query<&world::GridPos, Without<world::Solid>>,
-    mut q: Query<(&mut IsoCamera, &mut CameraSpin, &mut Transform)>,
+    mut q: Query<(&mut IsoCamera, &mut CameraSpin)>,
 ) {
-    let Ok((mut iso, mut spin, mut tform)) = q.single_mut() else { panic!() };    
-    
-    let target = {
-        let gp = player_q.single().unwrap();
-        world::grid_to_iso(gp.x, gp.y, constants::TILE_W, constants::TILE_H)
-    };
-    // If we’re mid-spin, advance it.
-    if spin.t < spin.duration {
+    for (mut iso, mut spin) in &mut q {
+        // Only advance if we’re mid-spin.
+        if spin.t >= spin.duration { continue; }
+
         spin.t += time.delta_secs();
         let alpha = (spin.t / spin.duration).clamp(0.0, 1.0);
         let eased = ease_in_out_cubic(alpha);
 
         let yaw = lerp_angle_deg(spin.start_yaw, spin.end_yaw, eased);
         iso.yaw_deg = yaw.rem_euclid(360.0);
-        // *tform = iso_camera_transform(iso.yaw_deg, iso.pitch_deg, iso.radius);
-        *tform = iso_camera_transform_at(target, iso.yaw_deg, iso.pitch_deg, iso.radius);
-
 
         // Finished this step?
         if alpha >= 1.0 {
             iso.yaw_deg = snap_to_quarter_turns(iso.yaw_deg);
-            // *tform = iso_camera_transform(iso.yaw_deg, iso.pitch_deg, iso.radius);
-            *tform = iso_camera_transform_at(target, iso.yaw_deg, iso.pitch_deg, iso.radius);
 
             // Launch next queued step if any.
             if spin.queued_steps != 0 {
@@ -101,7 +164,110 @@ pub fn animate_camera_spin(
                 spin.t = 0.0;
             }
         }
-    } 
+    }
+}
+
+/// Eases `IsoCamera::zoom` toward its target and applies it to the projection.
+pub fn animate_zoom(
+    time: Res<Time>,
+    mut q: Query<(&mut IsoCamera, &mut Projection)>,
+) {
+    // Exponential approach: frame-rate independent, no overshoot.
+    let alpha = 1.0 - (-12.0 * time.delta_secs()).exp();
+    for (mut iso, mut proj) in &mut q {
+        if iso.zoom == iso.zoom_target { continue; }
+        iso.zoom += (iso.zoom_target - iso.zoom) * alpha;
+        if (iso.zoom - iso.zoom_target).abs() < 1e-3 { iso.zoom = iso.zoom_target; }
+
+        if let Projection::Orthographic(ortho) = &mut *proj {
+            ortho.scaling_mode = ScalingMode::FixedVertical { viewport_height: iso.zoom };
+        }
+    }
+}
+
+/// Keeps the minimap's viewport proportional to the main view when linked.
+pub fn sync_minimap_zoom(
+    iso_q: Query<&IsoCamera, Changed<IsoCamera>>,
+    mut mini_q: Query<(&MinimapCamera, &mut Projection), Without<IsoCamera>>,
+) {
+    let Ok(iso) = iso_q.single() else { return };
+    for (mini, mut proj) in &mut mini_q {
+        let viewport_height = match mini.zoom_ratio {
+            Some(ratio) => iso.zoom * ratio,
+            None => mini.viewport_height,
+        };
+        if let Projection::Orthographic(ortho) = &mut *proj {
+            ortho.scaling_mode = ScalingMode::FixedVertical { viewport_height };
+        }
+    }
+}
+
+/* ---------------- Follow ---------------- */
+
+/// Puts the follow focus right on the player when a camera starts following
+/// and whenever a level is (re)built, rather than springing in from afar.
+pub fn snap_follow_to_player(
+    mut built: MessageReader<level::LevelBuilt>,
+    player_q: Query<&world::GridPos, With<world::LocalPlayer>>,
+    mut cam_q: Query<&mut CameraFollow>,
+) {
+    let rebuilt = built.read().count() > 0;
+    let Ok(gp) = player_q.single() else { return };
+    let target = world::grid_to_iso(gp.x, gp.y, constants::TILE_W, constants::TILE_H);
+    for mut f in &mut cam_q {
+        if rebuilt || f.is_added() {
+            f.snap_to(target);
+        }
+    }
+}
+
+/// Springs `CameraFollow::focus` toward the player (plus look-ahead in the
+/// direction of motion, minus a dead zone) and places the camera on its iso
+/// orbit around that focus.
+///
+/// The motion comes from the player's `MoveIntent` rather than from how far
+/// they moved since last frame: `GridPos` only changes on fixed ticks, so
+/// above the tick rate most frames would see no motion at all.
+pub fn follow_center_smooth(
+    time: Res<Time>,
+    player_q: Query<(&world::GridPos, Option<&MoveIntent>), With<world::LocalPlayer>>,
+    mut cam_q: Query<(&IsoCamera, &mut Transform, &mut CameraFollow)>,
+) {
+    // Clamp so a long frame (window drag, load hitch) cannot blow up the spring.
+    let dt = time.delta_secs().min(0.05);
+    let Ok((gp, intent)) = player_q.single() else { return };
+    let target = world::grid_to_iso(gp.x, gp.y, constants::TILE_W, constants::TILE_H);
+    // Grid +y is world -z (see `world::grid_to_iso`).
+    let vel = intent.map_or(Vec2::ZERO, |i| i.vel);
+    let heading = Vec3::new(vel.x, 0.0, -vel.y).normalize_or_zero();
+    let ease = 1.0 - (-LOOK_AHEAD_EASE * dt).exp();
+
+    for (iso, mut cam_tf, mut f) in &mut cam_q {
+        let ahead = heading * f.look_ahead;
+        f.ahead = f.ahead.lerp(ahead, ease);
+        let desired = target + f.ahead;
+
+        // Dead zone, measured along the screen's right/up directions on the ground
+        // (same axes as `collision::screen_to_grid`, in world XZ).
+        let yaw = iso.yaw_deg.to_radians();
+        let right = Vec3::new(yaw.sin(), 0.0, -yaw.cos());
+        let up = Vec3::new(-yaw.cos(), 0.0, -yaw.sin());
+        let off = desired - f.focus;
+        let outside = |axis: Vec3, half: f32| {
+            let d = off.dot(axis);
+            d - d.clamp(-half, half)
+        };
+        let mut goal = f.focus + right * outside(right, f.dead_zone.x) + up * outside(up, f.dead_zone.y);
+        goal.y = desired.y;
+
+        // critically-damped spring to goal
+        let accel = (goal - f.focus) * f.stiffness - f.vel * f.damping;
+        let vel = f.vel + accel * dt;
+        f.vel = vel;
+        f.focus += vel * dt;
+
+        *cam_tf = iso_camera_transform_at(f.focus, iso.yaw_deg, iso.pitch_deg, iso.radius);
+    }
 }
 
 /// Call this every frame (or whenever yaw changes):
@@ -109,12 +275,12 @@ pub fn animate_camera_spin(
 /// - Rotates its "up" around Y by the same yaw as the iso camera.
 ///   Using `up = rotate_y(-Z, yaw)` turns the minimap by the identical 90° steps.
 pub fn sync_minimap_to_iso_yaw(
-    player_q: Query<(&world::GridPos), Without<world::Solid>>,
+    player_q: Query<&world::GridPos, With<world::LocalPlayer>>,
     iso_q: Query<&IsoCamera>,
     mut mini_q: Query<(&MinimapCamera, &mut Transform)>,
 ) {
-    let gp = player_q.single().unwrap();
-    let iso = iso_q.single().unwrap();
+    let Ok(gp) = player_q.single() else { return };
+    let Ok(iso) = iso_q.single() else { return };
     let yaw = (iso.yaw_deg - 45.0).to_radians();    
 
     // Up vector rotated around Y by yaw (start from -Z for Cartesian feel)
@@ -159,54 +325,129 @@ fn lerp_angle_deg(a: f32, b: f32, t: f32) -> f32 {
 }
 
 /// Snap to exact 45° + n·90° if you want clean quadrants (optional).
-fn snap_to_quarter_turns(yaw: f32) -> f32 {
+pub fn snap_to_quarter_turns(yaw: f32) -> f32 {
     // Base at 45°, step 90°
     let rel = yaw - 45.0;
     let snapped = (rel / 90.0).round() * 90.0 + 45.0;
     snapped.rem_euclid(360.0)
 }
 
-/* ------ DEPRECATED ----------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::time::TimeUpdateStrategy;
+    use std::time::Duration;
+
+    /// An app with a local player at grid `(x, y)` and a following iso
+    /// camera, stepping 1/120 s per update (faster than the fixed tick).
+    fn follow_app(x: f32, y: f32) -> (App, Entity, Entity) {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(1.0 / 120.0)))
+            .add_message::<level::LevelBuilt>()
+            .add_systems(Update, (snap_follow_to_player, follow_center_smooth).chain());
+        let player = app
+            .world_mut()
+            .spawn((world::LocalPlayer, world::GridPos { x, y }, MoveIntent::default()))
+            .id();
+        let iso = IsoCamera {
+            yaw_deg: 45.0,
+            pitch_deg: 35.264,
+            radius: 20.0,
+            zoom: 10.0,
+            zoom_target: 10.0,
+            zoom_min: 4.0,
+            zoom_max: 20.0,
+        };
+        let cam = app.world_mut().spawn((iso, Transform::default(), CameraFollow::critically_damped(20.0))).id();
+        // The first update only gets the clock going.
+        app.update();
+        app.update();
+        (app, player, cam)
+    }
 
-pub fn follow_center_snap(
-    player_q: Query<(&world::GridPos), Without<world::Solid>>,
-    mut cam_q: Query<(&IsoCamera, &mut Transform)>,
-) {
-    let gp = player_q.single().unwrap();
-    let target = world::grid_to_iso(gp.x, gp.y, constants::TILE_W, constants::TILE_H);
+    fn follow(app: &App, cam: Entity) -> &CameraFollow {
+        app.world().get::<CameraFollow>(cam).unwrap()
+    }
 
-    for (iso, mut cam_tf) in &mut cam_q {
-        *cam_tf = iso_camera_transform_at(target, iso.yaw_deg, iso.pitch_deg, iso.radius);
+    fn run(app: &mut App, seconds: f32) {
+        for _ in 0..(seconds * 120.0) as usize {
+            app.update();
+        }
     }
-}
 
-pub fn follow_center_smooth(
-    time: Res<Time>,    
-    player_q: Query<(&world::GridPos), Without<world::Solid>>,
-    mut cam_q: Query<(&IsoCamera, &mut Transform, &mut CameraFollow)>,
-) {
-    let dt = time.delta_secs();
-    let gp = player_q.single().unwrap();
-    let target = world::grid_to_iso(gp.x, gp.y, constants::TILE_W, constants::TILE_H);     
-    println!("gp ~ {}, {}", gp.x, gp.y);
-    let (iso, mut cam_tf, mut f) = cam_q.single_mut().unwrap();
-    // desired camera position along iso orbit
-    // let yaw = iso.yaw_deg.to_radians();
-    // let pitch = iso.pitch_deg.to_radians();
-    // let dir = Vec3::new(yaw.cos() * pitch.cos(), pitch.sin(), yaw.sin() * pitch.cos());
-    let desired = target;
-
-    // critically-damped spring to desired
-    let k = f.stiffness;       // e.g. 20.0
-    let c = f.damping;         // e.g. 2.0 * (k).sqrt() or just ~10.0
-    let mut pos = cam_tf.translation;
-    let mut vel = f.vel;
-
-    let accel = (desired - pos) * k - vel * c;
-    vel += accel * dt;
-    // pos += vel * dt;
-
-    f.vel = vel;
-    // cam_tf.translation = pos;
-    // cam_tf.look_at(target, Vec3::Y);
+    #[test]
+    fn follow_starts_on_the_player() {
+        let (mut app, player, cam) = follow_app(10.0, 5.0);
+        let focus = |app: &App| follow(app, cam).focus;
+
+        let spawn = world::grid_to_iso(10.0, 5.0, constants::TILE_W, constants::TILE_H);
+        assert!(focus(&app).abs_diff_eq(spawn, 1e-4), "focus at {}", focus(&app));
+
+        // A level reload teleports the player; the camera goes with it.
+        *app.world_mut().get_mut::<world::GridPos>(player).unwrap() = world::GridPos { x: -3.0, y: 2.0 };
+        app.world_mut().write_message(level::LevelBuilt);
+        app.update();
+        let respawn = world::grid_to_iso(-3.0, 2.0, constants::TILE_W, constants::TILE_H);
+        assert!(focus(&app).abs_diff_eq(respawn, 1e-4), "focus at {}", focus(&app));
+    }
+
+    #[test]
+    fn camera_stays_put_inside_the_dead_zone() {
+        let (mut app, player, cam) = follow_app(10.0, 5.0);
+        let start = follow(&app, cam).focus;
+
+        // A small shuffle, well inside the 0.5 dead zone on both screen axes.
+        *app.world_mut().get_mut::<world::GridPos>(player).unwrap() = world::GridPos { x: 10.3, y: 5.0 };
+        run(&mut app, 1.0);
+        assert_eq!(follow(&app, cam).focus, start);
+    }
+
+    #[test]
+    fn camera_follows_out_of_the_dead_zone() {
+        let (mut app, player, cam) = follow_app(10.0, 5.0);
+        let start = follow(&app, cam).focus;
+
+        *app.world_mut().get_mut::<world::GridPos>(player).unwrap() = world::GridPos { x: 16.0, y: 5.0 };
+        run(&mut app, 3.0);
+        let target = world::grid_to_iso(16.0, 5.0, constants::TILE_W, constants::TILE_H);
+        let focus = follow(&app, cam).focus;
+        assert!(focus.x > start.x + 5.0, "focus at {focus}");
+        // Settled at the dead zone's edge (its corner is √2 · 0.5 away at most).
+        assert!(focus.distance(target) <= 0.5 * 2.0_f32.sqrt() + 0.05, "focus at {focus}");
+    }
+
+    #[test]
+    fn look_ahead_eases_toward_the_motion() {
+        let (mut app, player, cam) = follow_app(10.0, 5.0);
+        let target = world::grid_to_iso(10.0, 5.0, constants::TILE_W, constants::TILE_H);
+        let set_vel = |app: &mut App, vel: Vec2| app.world_mut().get_mut::<MoveIntent>(player).unwrap().vel = vel;
+
+        // Walking grid +x, i.e. world +x. `GridPos` stays put between fixed
+        // ticks (none run here), yet the look-ahead builds up smoothly.
+        set_vel(&mut app, Vec2::new(4.0, 0.0));
+        let mut last = 0.0;
+        for _ in 0..60 {
+            app.update();
+            let ahead = follow(&app, cam).ahead;
+            assert!(ahead.x > last, "look-ahead went from {last} to {}", ahead.x);
+            last = ahead.x;
+        }
+        run(&mut app, 2.0);
+        let f = follow(&app, cam);
+        assert!(f.ahead.abs_diff_eq(Vec3::X * f.look_ahead, 1e-2), "ahead {}", f.ahead);
+        assert!(f.focus.x > target.x + 0.5, "focus at {} did not lead the player", f.focus);
+
+        // Stopping eases it back out, without a jump.
+        set_vel(&mut app, Vec2::ZERO);
+        app.update();
+        assert!(follow(&app, cam).ahead.x > 1.0);
+        run(&mut app, 2.0);
+        assert!(follow(&app, cam).ahead.length() < 1e-2);
+
+        // Grid +y is world -z.
+        set_vel(&mut app, Vec2::new(0.0, 4.0));
+        run(&mut app, 2.0);
+        assert!(follow(&app, cam).ahead.z < -1.0);
+    }
 }
@@ -3,96 +3,145 @@ use crate::world;
 use crate::constants;
 use crate::camera;
 
-/// Reads keyboard, updates the entity's GridPos in discrete tile steps.
-///
-/// Controls:
-/// - W/A/S/D = screen-aligned (↑/←/↓/→ on screen)
-///   W: (-1,-1), A: (-1,+1), S: (+1,+1), D: (+1,-1)
-/// - Arrow keys = grid axes (isometric diagonals)
-///   Up: (0,-1), Left: (-1,0), Down: (0,+1), Right: (+1,0)
-fn input_move_grid(
-    keys: Res<ButtonInput<KeyCode>>,
-) -> (f32, f32) {
-    let mut dx = 0.0;
-    let mut dy = 0.0;
-
-    // Screen-aligned moves (1 tile per key press)
-    // if keys.just_pressed(KeyCode::KeyW) { dx -= 1; dy -= 1; }
-    // if keys.just_pressed(KeyCode::KeyS) { dx += 1; dy += 1; }
-    // if keys.just_pressed(KeyCode::KeyA) { dx -= 1; dy += 1; }
-    // if keys.just_pressed(KeyCode::KeyD) { dx += 1; dy -= 1; }
-
-    if keys.just_pressed(KeyCode::KeyW) { dy += 1.0; }
-    if keys.just_pressed(KeyCode::KeyS) { dy -= 1.0; }
-    if keys.just_pressed(KeyCode::KeyA) { dx -= 1.0; }
-    if keys.just_pressed(KeyCode::KeyD) { dx += 1.0; }
-    
-    // Optional: grid-axis arrows
-    
-    if keys.just_pressed(KeyCode::ArrowUp)    { dy += 0.2; }
-    if keys.just_pressed(KeyCode::ArrowDown)  { dy -= 0.2; }
-    if keys.just_pressed(KeyCode::ArrowLeft)  { dx -= 0.2; }
-    if keys.just_pressed(KeyCode::ArrowRight) { dx += 0.2; }
-    
-    (dx, dy)
+/// What a player wants to do next simulation tick. Input systems (keys,
+/// click-to-move) write it every frame; `move_with_collision_system` (or the
+/// server, for remote players) turns it into `GridPos` changes at the fixed tick.
+#[derive(Component, Default, Clone, Copy, Debug, PartialEq)]
+pub struct MoveIntent {
+    pub vel: Vec2,   // continuous movement, grid units per second
+    pub step: IVec2, // whole-tile steps queued since the last tick
 }
 
-pub fn follow_player(
+/// Which axes the movement keys follow.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveFrame {
+    /// W / ↑ always walk screen-up, whatever the camera yaw.
+    #[default]
+    Camera,
+    /// Keys map straight onto grid axes (ignores Q/E spins).
+    Grid,
+}
+
+/// M toggles between camera-relative and grid-absolute movement.
+pub fn toggle_move_frame(keys: Res<ButtonInput<KeyCode>>, mut frame: ResMut<MoveFrame>) {
+    if keys.just_pressed(KeyCode::KeyM) {
+        *frame = match *frame {
+            MoveFrame::Camera => MoveFrame::Grid,
+            MoveFrame::Grid => MoveFrame::Camera,
+        };
+    }
+}
+
+/// Screen-space input (x = right, y = up) -> grid delta for a camera at `yaw_deg`.
+/// The yaw is snapped to its quarter turn, so mid-spin input flips over halfway.
+pub fn screen_to_grid(input: Vec2, yaw_deg: f32) -> Vec2 {
+    let yaw = camera::snap_to_quarter_turns(yaw_deg).to_radians();
+    // The camera sits at +(cos, sin)·yaw in world XZ and looks back at the target,
+    // so screen-up is the opposite direction. Grid y is world -z (see `world::grid_to_iso`).
+    let up = Vec2::new(-yaw.cos(), yaw.sin());
+    let right = Vec2::new(yaw.sin(), yaw.cos());
+    right * input.x + up * input.y
+}
+
+/// Reads keyboard, queues discrete tile steps on the local player's `MoveIntent`.
+///
+/// Controls: W/A/S/D = screen up/left/down/right, one tile per key press.
+/// In `MoveFrame::Grid`: W/S = grid +y/-y, A/D = grid -x/+x.
+pub fn input_move_grid(
     keys: Res<ButtonInput<KeyCode>>,
-    time: Res<Time>,
-    mut cam_q: Query<(&camera::IsoCamera, &mut Transform)>,
-    mut player_q: Query<&mut world::GridPos, Without<world::Solid>>, // entities that can move
-    // mut light_q: Query<&mut Transform, With<world::FollowLight>>,
+    frame: Res<MoveFrame>,
+    iso_q: Query<&camera::IsoCamera>,
+    mut intents: Query<&mut MoveIntent, With<world::LocalPlayer>>,
 ) {
-    let speed = 5.0; // units per second
-    let dx = speed * time.delta_secs();
-        
-    let Ok(mut player) = player_q.single_mut() else { panic!() };
-    let Ok((mut iso, mut cam_tf)) = cam_q.single_mut() else { panic!() };
-    // let Ok((mut light_tf)) = light_q.single_mut() else { panic!() };
-
-    
-    // light_tf.translation.x = target.x;
-    // light_tf.translation.z = target.z;
-
-    if keys.pressed(KeyCode::ArrowUp) {
-        cam_tf.translation.x += dx;
-        player.x += dx;
+    let mut input = Vec2::ZERO;
+
+    if keys.just_pressed(KeyCode::KeyW) { input.y += 1.0; }
+    if keys.just_pressed(KeyCode::KeyS) { input.y -= 1.0; }
+    if keys.just_pressed(KeyCode::KeyA) { input.x -= 1.0; }
+    if keys.just_pressed(KeyCode::KeyD) { input.x += 1.0; }
+
+    // Arrow keys are continuous movement, handled by `follow_player`.
+    if input == Vec2::ZERO { return; }
+
+    let step = match *frame {
+        MoveFrame::Grid => input,
+        // Screen axes are grid diagonals at iso yaws; round so we still land on tiles.
+        MoveFrame::Camera => {
+            let Ok(iso) = iso_q.single() else { return };
+            screen_to_grid(input, iso.yaw_deg).round()
+        }
+    };
+    for mut intent in &mut intents {
+        intent.step += step.as_ivec2();
     }
+}
+
+/// Moves `gp` by (dx, dy) unless that would overlap a blocked cell.
+/// Each axis is tried on its own, so pushing diagonally into a wall slides along it.
+/// Returns the delta that was actually applied.
+pub fn try_move(blocked: &world::Blocked, gp: &mut world::GridPos, dx: f32, dy: f32) -> Vec2 {
+    let half = constants::MOVER_HALF;
+    let start = Vec2::new(gp.x, gp.y);
 
-    if keys.pressed(KeyCode::ArrowDown) {
-        cam_tf.translation.x -= dx;
-        player.x -= dx;
+    if dx != 0.0 && !blocked.overlaps(gp.x + dx, gp.y, half) {
+        gp.x += dx;
+    }
+    if dy != 0.0 && !blocked.overlaps(gp.x, gp.y + dy, half) {
+        gp.y += dy;
     }
 
-    if keys.pressed(KeyCode::ArrowLeft) {
-        cam_tf.translation.z += dx;
-        player.y -= dx;
+    Vec2::new(gp.x, gp.y) - start
+}
+
+/// One simulation tick of movement: queued tile steps first, then `vel · dt`.
+/// This is the only place intents become positions, so the local player, the
+/// server and anything replaying inputs all move identically.
+pub fn simulate_move(blocked: &world::Blocked, gp: &mut world::GridPos, intent: &MoveIntent, dt: f32) {
+    if intent.step != IVec2::ZERO {
+        let step = intent.step.clamp(-IVec2::ONE, IVec2::ONE).as_vec2();
+        try_move(blocked, gp, step.x, step.y);
     }
-    if keys.pressed(KeyCode::ArrowRight) {
-        cam_tf.translation.z -= dx;        
-        player.y += dx;        
-    }    
+    let vel = intent.vel.clamp_length_max(constants::MOVE_SPEED);
+    try_move(blocked, gp, vel.x * dt, vel.y * dt);
 }
 
-pub fn move_with_collision_system(
-    blocked: Res<world::Blocked>,
+/// Arrow keys: continuous movement, written to the local player's `MoveIntent`.
+pub fn follow_player(
+    frame: Res<MoveFrame>,
     keys: Res<ButtonInput<KeyCode>>,
-    mut movers: Query<&mut world::GridPos, Without<world::Solid>>, // entities that can move
-) {    
-    let (dx, dy) = input_move_grid(keys);    
+    iso_q: Query<&camera::IsoCamera>,
+    mut intents: Query<&mut MoveIntent, With<world::LocalPlayer>>,
+) {
+    // Screen-space input: x = right, y = up.
+    let mut input = Vec2::ZERO;
+    if keys.pressed(KeyCode::ArrowUp)    { input.y += 1.0; }
+    if keys.pressed(KeyCode::ArrowDown)  { input.y -= 1.0; }
+    if keys.pressed(KeyCode::ArrowLeft)  { input.x -= 1.0; }
+    if keys.pressed(KeyCode::ArrowRight) { input.x += 1.0; }
 
-    if dx == 0.0 && dy == 0.0 { return; }    
+    let dir = match *frame {
+        // Legacy mapping: ↑/↓ = grid +x/-x, ←/→ = grid -y/+y.
+        MoveFrame::Grid => Vec2::new(input.y, input.x),
+        MoveFrame::Camera => match iso_q.single() {
+            Ok(iso) => screen_to_grid(input, iso.yaw_deg),
+            Err(_) => Vec2::ZERO,
+        },
+    };
 
-    for mut gp in &mut movers {
-        let next = (gp.x + dx, gp.y + dy);        
+    for mut intent in &mut intents {
+        intent.vel = dir * constants::MOVE_SPEED;
+    }
+}
 
-        // Simple tile collision
-        // if !blocked.0.contains(&next) {
-            gp.x = next.0;
-            gp.y = next.1;
-        // }
-        // else: blocked; optionally try sliding along one axis here
+/// Fixed tick: applies the local player's `MoveIntent` with collision.
+pub fn move_with_collision_system(
+    time: Res<Time>,
+    blocked: Res<world::Blocked>,
+    mut movers: Query<(&mut world::GridPos, &mut MoveIntent), With<world::LocalPlayer>>,
+) {    
+    for (mut gp, mut intent) in &mut movers {
+        simulate_move(&blocked, &mut gp, &intent, time.delta_secs());
+        intent.step = IVec2::ZERO;
     }
 }
 
@@ -103,4 +152,69 @@ pub fn sync_render_from_grid(
     for (gp, mut t) in &mut q {                
         t.translation = world::grid_to_iso(gp.x, gp.y, constants::TILE_W, constants::TILE_H);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walls(cells: &[(i32, i32)]) -> world::Blocked {
+        world::Blocked(cells.iter().copied().collect())
+    }
+
+    #[test]
+    fn diagonal_moves_slide_along_walls() {
+        // A wall along grid y = 1.
+        let blocked = walls(&[(-1, 1), (0, 1), (1, 1)]);
+        let mut gp = world::GridPos { x: 0.0, y: 0.0 };
+        let moved = try_move(&blocked, &mut gp, 0.3, 0.3);
+        assert_eq!(moved, Vec2::new(0.3, 0.0));
+        assert_eq!((gp.x, gp.y), (0.3, 0.0));
+    }
+
+    #[test]
+    fn corners_stop_both_axes() {
+        let blocked = walls(&[(1, 0), (0, 1), (1, 1)]);
+        let mut gp = world::GridPos { x: 0.0, y: 0.0 };
+        assert_eq!(try_move(&blocked, &mut gp, 0.3, 0.3), Vec2::ZERO);
+        assert_eq!((gp.x, gp.y), (0.0, 0.0));
+
+        // Backing out of the corner still works.
+        assert_eq!(try_move(&blocked, &mut gp, -0.3, -0.3), Vec2::new(-0.3, -0.3));
+    }
+
+    #[test]
+    fn diagonal_tile_steps_slide_too() {
+        let blocked = walls(&[(1, 0)]);
+        let mut gp = world::GridPos { x: 0.0, y: 0.0 };
+        let intent = MoveIntent { vel: Vec2::ZERO, step: IVec2::new(1, 1) };
+        simulate_move(&blocked, &mut gp, &intent, 1.0 / 60.0);
+        assert_eq!((gp.x, gp.y), (0.0, 1.0));
+    }
+
+    #[test]
+    fn wasd_stays_screen_relative_at_every_yaw() {
+        let (w, a, s, d) = (Vec2::Y, -Vec2::X, -Vec2::Y, Vec2::X);
+        // Grid steps for W, A, S, D (see `input_move_grid`); each spin turns them a quarter.
+        let table = [
+            (45.0, [(-1, 1), (-1, -1), (1, -1), (1, 1)]),
+            (135.0, [(1, 1), (-1, 1), (-1, -1), (1, -1)]),
+            (225.0, [(1, -1), (1, 1), (-1, 1), (-1, -1)]),
+            (315.0, [(-1, -1), (1, -1), (1, 1), (-1, 1)]),
+        ];
+        for (yaw, expected) in table {
+            let steps = [w, a, s, d].map(|key| {
+                let g = screen_to_grid(key, yaw).round();
+                (g.x as i32, g.y as i32)
+            });
+            assert_eq!(steps, expected, "yaw {yaw}");
+
+            // W walks the way the camera looks, flattened onto the ground (grid y is world -z).
+            let forward = camera::iso_camera_transform(yaw, 30.0, 10.0).forward();
+            let ground = Vec2::new(forward.x, -forward.z).normalize();
+            assert!(screen_to_grid(w, yaw).abs_diff_eq(ground, 1e-5), "yaw {yaw}");
+        }
+        // Mid-spin, input follows the nearest quarter turn.
+        assert_eq!(screen_to_grid(w, 80.0), screen_to_grid(w, 45.0));
+    }
+}
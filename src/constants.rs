@@ -1,2 +1,8 @@
 pub const TILE_W: f32 = 1.0;
-pub const TILE_H: f32 = 1.0;
\ No newline at end of file
+pub const TILE_H: f32 = 1.0;
+// Half-size of a mover's square footprint, in tiles (a bit under 0.5 so it fits through 1-tile gaps).
+pub const MOVER_HALF: f32 = 0.4;
+// Top walking speed, grid units per second (the server clamps client input to this).
+pub const MOVE_SPEED: f32 = 5.0;
+// Simulation / network tick rate.
+pub const TICK_HZ: f64 = 60.0;
@@ -0,0 +1,100 @@
+use bevy::{prelude::*, ui::RelativeCursorPosition, window::PrimaryWindow};
+use crate::camera;
+use crate::constants;
+use crate::minimap;
+use crate::world;
+
+/// The grid cell under the mouse, if the cursor is over the window and the ground.
+#[derive(Resource, Default, Debug)]
+pub struct HoveredTile(pub Option<(i32, i32)>);
+
+/// Left click on a tile.
+#[derive(Message, Clone, Copy, Debug)]
+pub struct TileClicked {
+    pub cell: (i32, i32),
+}
+
+/// Right click on a tile.
+#[derive(Message, Clone, Copy, Debug)]
+pub struct TileRightClicked {
+    pub cell: (i32, i32),
+}
+
+/// The cell whose ground (the y = 0 plane) `ray` hits first, if it hits the ground at all.
+pub fn tile_under_ray(ray: Ray3d) -> Option<(i32, i32)> {
+    let dist = ray.intersect_plane(Vec3::ZERO, InfinitePlane3d::new(Vec3::Y))?;
+    let ground = ray.get_point(dist);
+    let g = world::grid_from_iso_world(ground.x, ground.z, constants::TILE_W, constants::TILE_H);
+    Some(world::cell_at(g.x, g.y))
+}
+
+/// Casts the cursor ray from the `IsoCamera` onto the ground plane and maps the
+/// hit back to a cell with `world::grid_from_iso_world`. Because it uses the
+/// camera's actual transform it stays correct at every spin yaw (and mid-spin).
+/// Nothing is hovered while the cursor is over the minimap (it handles its own
+/// clicks, see `minimap::click_minimap`).
+pub fn update_hovered_tile(
+    mut hovered: ResMut<HoveredTile>,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    cam_q: Query<(&Camera, &GlobalTransform), With<camera::IsoCamera>>,
+    minimap_q: Query<&RelativeCursorPosition, With<minimap::MinimapFrame>>,
+) {
+    let cell = (|| {
+        if minimap_q.iter().any(|m| m.cursor_over()) { return None; }
+        let window = window_q.single().ok()?;
+        let (cam, cam_gt) = cam_q.single().ok()?;
+        tile_under_ray(cam.viewport_to_world(cam_gt, window.cursor_position()?).ok()?)
+    })();
+
+    if hovered.0 != cell {
+        hovered.0 = cell;
+    }
+}
+
+/// Turns mouse clicks over a tile into `TileClicked` / `TileRightClicked`.
+pub fn emit_tile_clicks(
+    buttons: Res<ButtonInput<MouseButton>>,
+    hovered: Res<HoveredTile>,
+    mut clicked: MessageWriter<TileClicked>,
+    mut right_clicked: MessageWriter<TileRightClicked>,
+) {
+    let Some(cell) = hovered.0 else { return };
+    if buttons.just_pressed(MouseButton::Left) {
+        clicked.write(TileClicked { cell });
+    }
+    if buttons.just_pressed(MouseButton::Right) {
+        right_clicked.write(TileRightClicked { cell });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The ray through the screen point over ground point (x, z), as the
+    /// orthographic `IsoCamera` at `yaw_deg` casts it.
+    fn ray_over(yaw_deg: f32, x: f32, z: f32) -> Ray3d {
+        let cam = camera::iso_camera_transform_at(Vec3::new(x, 0.0, z), yaw_deg, 30.0, 20.0);
+        Ray3d::new(cam.translation, cam.forward())
+    }
+
+    #[test]
+    fn picking_agrees_with_cells_at_every_yaw() {
+        for yaw in [45.0, 135.0, 225.0, 315.0] {
+            // Tile (2, 3) covers world x 2..3 and z -4..-3 (see `world::grid_to_iso`).
+            assert_eq!(tile_under_ray(ray_over(yaw, 2.5, -3.5)), Some((2, 3)), "centre at yaw {yaw}");
+            assert_eq!(tile_under_ray(ray_over(yaw, 2.01, -3.01)), Some((2, 3)), "inner corner at yaw {yaw}");
+            assert_eq!(tile_under_ray(ray_over(yaw, 2.99, -3.99)), Some((2, 3)), "outer corner at yaw {yaw}");
+            // Just across each edge.
+            assert_eq!(tile_under_ray(ray_over(yaw, 1.99, -3.5)), Some((1, 3)), "yaw {yaw}");
+            assert_eq!(tile_under_ray(ray_over(yaw, 3.01, -3.5)), Some((3, 3)), "yaw {yaw}");
+            assert_eq!(tile_under_ray(ray_over(yaw, 2.5, -2.99)), Some((2, 2)), "yaw {yaw}");
+            assert_eq!(tile_under_ray(ray_over(yaw, 2.5, -4.01)), Some((2, 4)), "yaw {yaw}");
+        }
+    }
+
+    #[test]
+    fn rays_that_miss_the_ground_pick_nothing() {
+        assert_eq!(tile_under_ray(Ray3d::new(Vec3::new(0.0, 5.0, 0.0), Dir3::Y)), None);
+    }
+}
@@ -0,0 +1,329 @@
+//! Fog of war on the minimap. Tiles we have never seen are hidden, tiles we
+//! saw before are dimmed, and tiles in view (within [`SIGHT_RADIUS`] and not
+//! behind a wall) show as normal. The fog is a tile-sized texture on a plane
+//! above the level that only the minimap camera renders ([`FOG_LAYER`]).
+//!
+//! What we explored is kept in the save game (see `save`). Players with a
+//! `world::Team` also explore whatever their teammates do, via the server.
+use bevy::{
+    prelude::*,
+    asset::RenderAssetUsages,
+    camera::visibility::RenderLayers,
+    image::ImageSampler,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use crate::level;
+use crate::save;
+use crate::states::AppState;
+use crate::world;
+
+/// How far we see, in tiles.
+pub const SIGHT_RADIUS: i32 = 6;
+/// Render layer of the fog plane; the minimap camera sees it, the main one doesn't.
+pub const FOG_LAYER: usize = 1;
+/// Just above the walls (1 tile tall).
+const FOG_HEIGHT: f32 = 1.05;
+const UNEXPLORED: [u8; 4] = [8, 8, 12, 255];
+const EXPLORED: [u8; 4] = [8, 8, 12, 150];
+const VISIBLE: [u8; 4] = [0, 0, 0, 0];
+
+/// What we know about a tile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileFog {
+    Unexplored,
+    Explored,
+    Visible,
+}
+
+/// Our view of the current level, one entry per tile (row-major, like `level::Level`).
+#[derive(Resource, Debug, Default)]
+pub struct FogOfWar {
+    pub width: usize,
+    pub height: usize,
+    explored: Vec<bool>,
+    visible: Vec<bool>,
+    /// Where we last looked from; we only look again after moving to another tile.
+    seen_from: Option<(i32, i32)>,
+    /// Tiles we explored that our team hasn't heard about yet.
+    pub unshared: Vec<(i32, i32)>,
+}
+
+impl FogOfWar {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            explored: vec![false; width * height],
+            visible: vec![false; width * height],
+            ..default()
+        }
+    }
+
+    fn index(&self, (x, y): (i32, i32)) -> Option<usize> {
+        let inside = (0..self.width as i32).contains(&x) && (0..self.height as i32).contains(&y);
+        inside.then(|| y as usize * self.width + x as usize)
+    }
+
+    pub fn tile(&self, cell: (i32, i32)) -> TileFog {
+        match self.index(cell) {
+            Some(i) if self.visible[i] => TileFog::Visible,
+            Some(i) if self.explored[i] => TileFog::Explored,
+            _ => TileFog::Unexplored,
+        }
+    }
+
+    /// Sees everything within [`SIGHT_RADIUS`] of `from` that no wall hides
+    /// (walls themselves are seen). Returns the tiles explored for the first time.
+    pub fn look_from(&mut self, blocked: &world::Blocked, from: (i32, i32)) -> Vec<(i32, i32)> {
+        self.seen_from = Some(from);
+        self.visible.fill(false);
+        let mut new = Vec::new();
+        let r = SIGHT_RADIUS;
+        for dy in -r..=r {
+            for dx in -r..=r {
+                if dx * dx + dy * dy > r * r { continue; }
+                let cell = (from.0 + dx, from.1 + dy);
+                let Some(i) = self.index(cell) else { continue };
+                if !line_of_sight(blocked, from, cell) { continue; }
+                self.visible[i] = true;
+                if !self.explored[i] {
+                    self.explored[i] = true;
+                    new.push(cell);
+                }
+            }
+        }
+        new
+    }
+
+    /// Marks tiles explored by someone else (a teammate, or a save game).
+    pub fn explore(&mut self, cells: impl IntoIterator<Item = (i32, i32)>) {
+        for cell in cells {
+            if let Some(i) = self.index(cell) {
+                self.explored[i] = true;
+            }
+        }
+    }
+
+    /// Explored tiles as text rows, `#` explored and `.` not (row 0 is grid y = 0).
+    pub fn explored_rows(&self) -> Vec<String> {
+        self.explored
+            .chunks(self.width.max(1))
+            .map(|row| row.iter().map(|&e| if e { '#' } else { '.' }).collect())
+            .collect()
+    }
+
+    /// Undoes [`explored_rows`](Self::explored_rows). Rows that don't fit
+    /// our size (a save from before the level was resized) are ignored, and
+    /// we return false.
+    pub fn explore_rows(&mut self, rows: &[String]) -> bool {
+        let fits = rows.len() == self.height && rows.iter().all(|row| row.chars().count() == self.width);
+        if !fits { return false; }
+        for (y, row) in rows.iter().enumerate() {
+            let cells = row.chars().enumerate().filter(|&(_, c)| c == '#').map(|(x, _)| (x as i32, y as i32));
+            self.explore(cells);
+        }
+        true
+    }
+}
+
+/// Tiles as they go over the wire (see `net::protocol`), dropping any too far
+/// out for an `i16`.
+pub fn cells_to_wire(cells: impl IntoIterator<Item = (i32, i32)>) -> Vec<[i16; 2]> {
+    cells
+        .into_iter()
+        .filter_map(|(x, y)| Some([i16::try_from(x).ok()?, i16::try_from(y).ok()?]))
+        .collect()
+}
+
+/// Undoes [`cells_to_wire`].
+pub fn cells_from_wire(cells: &[[i16; 2]]) -> impl Iterator<Item = (i32, i32)> + '_ {
+    cells.iter().map(|&[x, y]| (x as i32, y as i32))
+}
+
+/// Whether nothing blocks the straight line between the two cells' centres
+/// (the cells themselves may be walls).
+fn line_of_sight(blocked: &world::Blocked, from: (i32, i32), to: (i32, i32)) -> bool {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let steps = dx.abs().max(dy.abs());
+    (1..steps).all(|i| {
+        let t = i as f32 / steps as f32;
+        let cell = ((from.0 as f32 + dx as f32 * t).round() as i32, (from.1 as f32 + dy as f32 * t).round() as i32);
+        !blocked.is_blocked(cell)
+    })
+}
+
+/// The fog texture: one pixel per tile.
+#[derive(Resource, Debug)]
+pub struct FogTexture(pub Handle<Image>);
+
+/// Marks the fog plane.
+#[derive(Component, Debug)]
+pub struct FogPlane;
+
+/// On `level::LevelBuilt`: fits the fog to the level, bringing back what the
+/// save game says we explored (or keeping what we had, if the level was
+/// only reloaded), and lays the fog plane over it.
+#[allow(clippy::too_many_arguments)]
+pub fn reset_fog(
+    mut commands: Commands,
+    mut built: MessageReader<level::LevelBuilt>,
+    current: Option<Res<level::CurrentLevel>>,
+    levels: Res<Assets<level::Level>>,
+    mut fog: ResMut<FogOfWar>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    old: Query<Entity, With<FogPlane>>,
+) {
+    if built.read().count() == 0 { return; }
+    let Some(current) = current else { return };
+    let Some(level) = levels.get(&current.0) else { return };
+
+    if (fog.width, fog.height) != (level.width, level.height) {
+        *fog = FogOfWar::new(level.width, level.height);
+        if let Some(save) = save::SaveGame::load(&current.name()) {
+            if !fog.explore_rows(&save.explored) {
+                warn!("ignoring the explored tiles saved for {}: they don't fit the level", current.name());
+            }
+        }
+    }
+    fog.seen_from = None;
+
+    for e in &old {
+        commands.entity(e).despawn();
+    }
+    let size = Extent3d { width: level.width as u32, height: level.height as u32, depth_or_array_layers: 1 };
+    let mut image = Image::new_fill(size, TextureDimension::D2, &UNEXPLORED, TextureFormat::Rgba8UnormSrgb, RenderAssetUsages::default());
+    image.sampler = ImageSampler::nearest();
+    let image = images.add(image);
+    let (w, h) = (level.width as f32, level.height as f32);
+    commands.spawn((
+        DespawnOnExit(AppState::InGame),
+        FogPlane,
+        Mesh3d(meshes.add(Plane3d::default().mesh().size(w, h))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color_texture: Some(image.clone()),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        })),
+        // Grid (0, 0) is world x 0..1, z -1..0 (see `world::grid_to_iso`).
+        Transform::from_xyz(w / 2.0, FOG_HEIGHT, -h / 2.0),
+        RenderLayers::layer(FOG_LAYER),
+    ));
+    commands.insert_resource(FogTexture(image));
+}
+
+/// Looks around whenever the local player reaches another tile, noting new
+/// tiles for the team if we have one.
+pub fn look_around(
+    blocked: Res<world::Blocked>,
+    mut fog: ResMut<FogOfWar>,
+    player_q: Query<(&world::GridPos, Option<&world::Team>), With<world::LocalPlayer>>,
+) {
+    let Ok((gp, team)) = player_q.single() else { return };
+    let cell = world::cell_at(gp.x, gp.y);
+    if fog.seen_from == Some(cell) || fog.width == 0 { return; }
+    let new = fog.look_from(&blocked, cell);
+    if team.is_some() {
+        fog.unshared.extend(new);
+    }
+}
+
+/// Repaints the fog texture after the fog changed.
+pub fn paint_fog(fog: Res<FogOfWar>, texture: Option<Res<FogTexture>>, mut images: ResMut<Assets<Image>>) {
+    let Some(texture) = texture else { return };
+    if !fog.is_changed() && !texture.is_changed() { return; }
+    let Some(data) = images.get_mut(&texture.0).and_then(|image| image.data.as_mut()) else { return };
+    // Texture row 0 is the far (-z) edge of the plane: the level's last row.
+    for y in 0..fog.height {
+        for x in 0..fog.width {
+            let pixel = match fog.tile((x as i32, y as i32)) {
+                TileFog::Unexplored => UNEXPLORED,
+                TileFog::Explored => EXPLORED,
+                TileFog::Visible => VISIBLE,
+            };
+            let i = ((fog.height - 1 - y) * fog.width + x) * 4;
+            data[i..i + 4].copy_from_slice(&pixel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walls(cells: &[(i32, i32)]) -> world::Blocked {
+        world::Blocked(cells.iter().copied().collect())
+    }
+
+    #[test]
+    fn walls_hide_what_is_behind_them() {
+        let blocked = walls(&[(5, 3), (5, 4), (5, 5)]);
+        let mut fog = FogOfWar::new(20, 10);
+        fog.look_from(&blocked, (3, 4));
+
+        assert_eq!(fog.tile((3, 4)), TileFog::Visible);
+        assert_eq!(fog.tile((5, 4)), TileFog::Visible, "the wall itself is seen");
+        assert_eq!(fog.tile((7, 4)), TileFog::Unexplored, "behind the wall");
+        assert_eq!(fog.tile((3, 4 + SIGHT_RADIUS + 1)), TileFog::Unexplored, "too far");
+        assert_eq!(fog.tile((-1, 4)), TileFog::Unexplored, "off the level");
+    }
+
+    #[test]
+    fn explored_tiles_stay_dimmed() {
+        let open = walls(&[]);
+        let mut fog = FogOfWar::new(30, 5);
+        assert!(fog.look_from(&open, (2, 2)).contains(&(2, 2)));
+
+        let new = fog.look_from(&open, (6, 2));
+        assert!(!new.contains(&(4, 2)), "seen already");
+        assert!(new.contains(&(12, 2)));
+
+        fog.look_from(&open, (20, 2));
+        assert_eq!(fog.tile((2, 2)), TileFog::Explored);
+        assert_eq!(fog.tile((20, 2)), TileFog::Visible);
+        assert_eq!(fog.tile((13, 2)), TileFog::Unexplored);
+    }
+
+    #[test]
+    fn explored_rows_round_trip() {
+        let mut fog = FogOfWar::new(4, 3);
+        fog.explore([(0, 0), (3, 0), (1, 2)]);
+        let rows = fog.explored_rows();
+        assert_eq!(rows, ["#..#", "....", ".#.."]);
+
+        let mut loaded = FogOfWar::new(4, 3);
+        assert!(loaded.explore_rows(&rows));
+        assert_eq!(loaded.explored_rows(), rows);
+    }
+
+    #[test]
+    fn saves_for_another_size_are_ignored() {
+        let rows = vec!["####".to_string(); 3];
+        for (w, h) in [(3, 3), (5, 3), (4, 2), (4, 4)] {
+            let mut fog = FogOfWar::new(w, h);
+            assert!(!fog.explore_rows(&rows), "{w}x{h}");
+            assert_eq!(fog.tile((0, 0)), TileFog::Unexplored, "{w}x{h}");
+        }
+        let mut ragged = rows.clone();
+        ragged[1].push('#');
+        assert!(!FogOfWar::new(4, 3).explore_rows(&ragged));
+    }
+
+    #[test]
+    fn teammates_tiles_arrive_explored() {
+        let open = walls(&[]);
+        let mut ours = FogOfWar::new(20, 20);
+        let new = ours.look_from(&open, (4, 4));
+        let wire = cells_to_wire(new.iter().copied().chain([(70_000, 1)]));
+        assert_eq!(wire.len(), new.len(), "cells that don't fit an i16 are dropped");
+
+        let mut theirs = FogOfWar::new(20, 20);
+        theirs.look_from(&open, (15, 15));
+        theirs.explore(cells_from_wire(&wire));
+        assert_eq!(theirs.tile((4, 4)), TileFog::Explored, "seen by a teammate, not by us");
+        assert_eq!(theirs.tile((15, 15)), TileFog::Visible);
+        assert_eq!(theirs.tile((4, 4 + SIGHT_RADIUS + 1)), TileFog::Unexplored);
+    }
+}
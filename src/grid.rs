@@ -1,6 +1,8 @@
 use bevy::{prelude::*};
 use crate::constants::TILE_W;
 use crate::constants::TILE_H;
+use crate::cursor;
+use crate::world;
 /// Draws a grid on the XZ plane using gizmos.
 /// - `extent` controls how far the grid goes in tiles
 /// - `y_level` lets you offset the grid up/down if you like
@@ -36,4 +38,17 @@ pub fn draw_grid_gizmos(mut gizmos: Gizmos) {
             color,
         );
     }        
-}
\ No newline at end of file
+}
+
+/// Outlines the tile under the cursor, just above the grid lines.
+pub fn draw_hovered_tile(mut gizmos: Gizmos, hovered: Res<cursor::HoveredTile>) {
+    let Some((cx, cy)) = hovered.0 else { return };
+    let mut center = world::grid_to_iso(cx as f32, cy as f32, TILE_W, TILE_H);
+    center.y = 0.01;
+
+    gizmos.rect(
+        Isometry3d::new(center, Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2)),
+        Vec2::new(TILE_W, TILE_H),
+        Color::srgb(1.0, 0.85, 0.2),
+    );
+}
@@ -0,0 +1,463 @@
+use bevy::{
+    prelude::*,
+    asset::{io::Reader, AssetLoader, LoadContext, LoadState},
+    ecs::system::SystemParam,
+};
+use serde::Deserialize;
+use crate::constants;
+use crate::world;
+use crate::states::AppState;
+
+/// Level loaded at startup (relative to `assets/`), unless `--level` names another.
+pub const START_LEVEL: &str = "levels/start.level.ron";
+
+/// `--level <path>` (relative to `assets/`), or [`START_LEVEL`]. Either a
+/// `.level.ron` file or a Tiled map (`.tmj` / `.tmx`, see `tiled`): the asset
+/// server picks the loader by extension. A host and its clients should all
+/// pass the same one.
+pub fn start_level() -> String {
+    crate::net::arg_value("--level").unwrap_or_else(|| START_LEVEL.to_string())
+}
+
+/* ---------------- Level data ---------------- */
+
+/// What occupies a single grid cell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileKind {
+    Floor,
+    Wall,
+}
+
+impl TileKind {
+    /// Tile codes used in `.level.ron` rows.
+    /// - `.` or `0` = floor
+    /// - `#` or `1` = wall (blocks movement, spawns a `world::Solid` cube)
+    pub fn from_code(code: char) -> Option<Self> {
+        match code {
+            '.' | '0' => Some(TileKind::Floor),
+            '#' | '1' => Some(TileKind::Wall),
+            _ => None,
+        }
+    }
+}
+
+/// A named place where something (usually the player) starts.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SpawnPoint {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// A decorative scene (e.g. a `.glb`) placed on the grid.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Prop {
+    pub model: String, // asset path, e.g. "models/resource.glb#Scene0"
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub rotation_deg: f32,
+}
+
+/// A parsed, validated level. Row 0 is grid y = 0, column 0 is grid x = 0.
+#[derive(Asset, TypePath, Debug)]
+pub struct Level {
+    pub width: usize,
+    pub height: usize,
+    pub tiles: Vec<TileKind>, // row-major, width * height
+    pub spawns: Vec<SpawnPoint>,
+    pub props: Vec<Prop>,
+}
+
+impl Level {
+    pub fn tile(&self, gx: usize, gy: usize) -> TileKind {
+        self.tiles[gy * self.width + gx]
+    }
+
+    /// All wall cells, in `world::Blocked` coordinates.
+    pub fn blocked_cells(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        (0..self.height)
+            .flat_map(move |gy| (0..self.width).map(move |gx| (gx, gy)))
+            .filter(|&(gx, gy)| self.tile(gx, gy) == TileKind::Wall)
+            .map(|(gx, gy)| (gx as i32, gy as i32))
+    }
+
+    pub fn spawn(&self, name: &str) -> Option<&SpawnPoint> {
+        self.spawns.iter().find(|s| s.name == name)
+    }
+}
+
+/// Raw file layout, before tile codes are checked.
+#[derive(Deserialize)]
+struct LevelFile {
+    width: usize,
+    height: usize,
+    tiles: Vec<String>,
+    #[serde(default)]
+    spawns: Vec<SpawnPoint>,
+    #[serde(default)]
+    props: Vec<Prop>,
+}
+
+impl Level {
+    /// Parses a `.level.ron` file. `path` is only used for error messages.
+    pub fn from_ron(path: &str, bytes: &[u8]) -> Result<Self, LevelLoadError> {
+        let file: LevelFile = ron::de::from_bytes(bytes).map_err(|e| LevelLoadError::Syntax {
+            path: path.to_string(),
+            line: e.position.line,
+            col: e.position.col,
+            msg: e.code.to_string(),
+        })?;
+
+        if file.tiles.len() != file.height {
+            return Err(LevelLoadError::RowCount {
+                path: path.to_string(),
+                expected: file.height,
+                found: file.tiles.len(),
+            });
+        }
+
+        let mut tiles = Vec::with_capacity(file.width * file.height);
+        for (row, line) in file.tiles.iter().enumerate() {
+            let len = line.chars().count();
+            if len != file.width {
+                return Err(LevelLoadError::RowWidth {
+                    path: path.to_string(),
+                    row,
+                    expected: file.width,
+                    found: len,
+                });
+            }
+            for (col, code) in line.chars().enumerate() {
+                let Some(kind) = TileKind::from_code(code) else {
+                    return Err(LevelLoadError::BadTile { path: path.to_string(), row, col, code });
+                };
+                tiles.push(kind);
+            }
+        }
+
+        for s in &file.spawns {
+            if s.x < 0 || s.y < 0 || s.x as usize >= file.width || s.y as usize >= file.height {
+                return Err(LevelLoadError::SpawnOutOfBounds {
+                    path: path.to_string(),
+                    name: s.name.clone(),
+                    x: s.x,
+                    y: s.y,
+                });
+            }
+        }
+
+        Ok(Level {
+            width: file.width,
+            height: file.height,
+            tiles,
+            spawns: file.spawns,
+            props: file.props,
+        })
+    }
+}
+
+/* ---------------- Errors ---------------- */
+
+/// Everything that can go wrong turning a level file into a [`Level`].
+/// Every variant names the file so the message is useful on its own in the log.
+#[derive(Debug)]
+pub enum LevelLoadError {
+    Io { path: String, source: std::io::Error },
+    Syntax { path: String, line: usize, col: usize, msg: String },
+    RowCount { path: String, expected: usize, found: usize },
+    RowWidth { path: String, row: usize, expected: usize, found: usize },
+    BadTile { path: String, row: usize, col: usize, code: char },
+    SpawnOutOfBounds { path: String, name: String, x: i32, y: i32 },
+}
+
+impl std::fmt::Display for LevelLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LevelLoadError::Io { path, source } => write!(f, "{path}: could not read level: {source}"),
+            LevelLoadError::Syntax { path, line, col, msg } => write!(f, "{path}:{line}:{col}: {msg}"),
+            LevelLoadError::RowCount { path, expected, found } => {
+                write!(f, "{path}: height is {expected} but `tiles` has {found} rows")
+            }
+            LevelLoadError::RowWidth { path, row, expected, found } => {
+                write!(f, "{path}: row {row} has {found} tiles, expected width {expected}")
+            }
+            LevelLoadError::BadTile { path, row, col, code } => {
+                write!(f, "{path}: unknown tile code {code:?} at row {row}, column {col}")
+            }
+            LevelLoadError::SpawnOutOfBounds { path, name, x, y } => {
+                write!(f, "{path}: spawn point {name:?} at ({x}, {y}) is outside the map")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LevelLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LevelLoadError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/* ---------------- Asset loader ---------------- */
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    type Asset = Level;
+    type Settings = ();
+    type Error = LevelLoadError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Level, LevelLoadError> {
+        let path = load_context.path().display().to_string();
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(|source| LevelLoadError::Io { path: path.clone(), source })?;
+        Level::from_ron(&path, &bytes)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+/* ---------------- Spawning ---------------- */
+
+/// The level currently shown in the world.
+#[derive(Resource)]
+pub struct CurrentLevel(pub Handle<Level>);
+
+impl CurrentLevel {
+    /// Short name of the level's file: "levels/start.level.ron" -> "start".
+    pub fn name(&self) -> String {
+        self.0
+            .path()
+            .and_then(|p| p.path().file_name())
+            .map(|f| f.to_string_lossy().split('.').next().unwrap_or_default().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// Tag for everything spawned from a level, so it can be cleared on reload.
+#[derive(Component)]
+pub struct LevelEntity;
+
+pub fn load_start_level(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(CurrentLevel(asset_server.load(start_level())));
+}
+
+/// Sent whenever a level has been (re)built, so the client can redraw it.
+#[derive(Message, Debug, Clone, Copy)]
+pub struct LevelBuilt;
+
+/// Everything the simulation needs to set up a [`Level`].
+#[derive(SystemParam)]
+pub struct LevelBuilder<'w, 's> {
+    blocked: ResMut<'w, world::Blocked>,
+    built: MessageWriter<'w, LevelBuilt>,
+    player_q: Query<'w, 's, &'static mut world::GridPos, With<world::LocalPlayer>>,
+}
+
+impl LevelBuilder<'_, '_> {
+    /// Replaces whatever level is in the world with `level`: fills `world::Blocked`
+    /// and moves the player to the `"player"` spawn point. The meshes are up to
+    /// [`spawn_level_visuals`].
+    fn build(&mut self, level: &Level) {
+        self.blocked.0.clear();
+        self.blocked.0.extend(level.blocked_cells());
+
+        if let Some(spawn) = level.spawn("player") {
+            for mut gp in &mut self.player_q {
+                gp.x = spawn.x as f32;
+                gp.y = spawn.y as f32;
+            }
+        }
+        self.built.write(LevelBuilt);
+    }
+}
+
+/// Client only: on [`LevelBuilt`], replaces the level's `world::Solid` cubes and props.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_level_visuals(
+    mut commands: Commands,
+    mut built: MessageReader<LevelBuilt>,
+    current: Option<Res<CurrentLevel>>,
+    levels: Res<Assets<Level>>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    old: Query<Entity, With<LevelEntity>>,
+) {
+    if built.read().count() == 0 { return; }
+    let Some(level) = current.and_then(|c| levels.get(&c.0)) else { return };
+
+    for e in &old {
+        commands.entity(e).despawn();
+    }
+
+    // Visualize blocked cells
+    let cube = meshes.add(Cuboid::new(1.0, 1.0, 1.0));
+    let wall = materials.add(Color::srgb(0.7, 0.4, 0.5));
+    for (x, y) in level.blocked_cells() {
+        let p = world::grid_to_iso(x as f32, y as f32, constants::TILE_W, constants::TILE_H);
+        commands.spawn((
+            LevelEntity,
+            DespawnOnExit(AppState::InGame),
+            world::Solid,
+            Mesh3d(cube.clone()),
+            MeshMaterial3d(wall.clone()),
+            Transform::from_translation(p),
+        ));
+    }
+
+    for prop in &level.props {
+        let p = world::grid_to_iso(prop.x, prop.y, constants::TILE_W, constants::TILE_H);
+        commands.spawn((
+            LevelEntity,
+            DespawnOnExit(AppState::InGame),
+            SceneRoot(asset_server.load(prop.model.clone())),
+            Transform::from_translation(p)
+                .with_rotation(Quat::from_rotation_y(prop.rotation_deg.to_radians())),
+        ));
+    }
+}
+
+/// `AppState::Loading`: move on once the level (and its props) are loaded,
+/// or back to the menu if it failed.
+pub fn wait_for_level(
+    current: Res<CurrentLevel>,
+    asset_server: Res<AssetServer>,
+    mut next: ResMut<NextState<AppState>>,
+) {
+    if asset_server.is_loaded_with_dependencies(&current.0) {
+        next.set(AppState::InGame);
+    } else if let Some(LoadState::Failed(err)) = asset_server.get_load_state(&current.0) {
+        error!("{err}");
+        next.set(AppState::Menu);
+    }
+}
+
+/// `OnEnter(AppState::InGame)`: builds the (already loaded) current level.
+pub fn spawn_level(current: Res<CurrentLevel>, levels: Res<Assets<Level>>, mut builder: LevelBuilder) {
+    if let Some(level) = levels.get(&current.0) {
+        builder.build(level);
+    }
+}
+
+/// Rebuilds the level whenever its file changes on disk.
+pub fn reload_level(
+    mut events: MessageReader<AssetEvent<Level>>,
+    current: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    mut builder: LevelBuilder,
+) {
+    let modified = events
+        .read()
+        .any(|ev| matches!(ev, AssetEvent::Modified { id } if *id == current.0.id()));
+    if !modified { return; }
+    if let Some(level) = levels.get(&current.0) {
+        builder.build(level);
+    }
+}
+
+/// `OnExit(AppState::InGame)`: entities go via `DespawnOnExit`, this clears the rest.
+pub fn unload_level(mut commands: Commands, mut blocked: ResMut<world::Blocked>) {
+    blocked.0.clear();
+    commands.remove_resource::<CurrentLevel>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATH: &str = "levels/test.level.ron";
+
+    fn load(text: &str) -> Result<Level, LevelLoadError> {
+        Level::from_ron(PATH, text.as_bytes())
+    }
+
+    #[test]
+    fn good_levels_load() {
+        let level = load(r##"(width: 3, height: 2, tiles: ["#.#", "0.1"], spawns: [(name: "player", x: 1, y: 1)])"##).unwrap();
+        assert_eq!((level.width, level.height), (3, 2));
+        assert_eq!(level.tile(0, 0), TileKind::Wall);
+        assert_eq!(level.tile(1, 1), TileKind::Floor);
+        assert_eq!(level.blocked_cells().collect::<Vec<_>>(), [(0, 0), (2, 0), (2, 1)]);
+        assert_eq!(level.spawn("player").map(|s| (s.x, s.y)), Some((1, 1)));
+    }
+
+    #[test]
+    fn the_start_level_loads() {
+        let level = Level::from_ron(START_LEVEL, include_bytes!("../assets/levels/start.level.ron")).unwrap();
+        assert!(level.spawn("player").is_some());
+    }
+
+    /// What `--level` relies on: each kind of level file finds its loader.
+    #[test]
+    fn levels_load_by_extension() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Level>()
+            .init_asset_loader::<LevelLoader>()
+            .init_asset_loader::<crate::tiled::TiledLoader>();
+        let server = app.world().resource::<AssetServer>().clone();
+        let handles = [START_LEVEL, "levels/sample.tmj"].map(|path| server.load::<Level>(path));
+        for _ in 0..1000 {
+            if handles.iter().all(|h| server.is_loaded(h) || server.load_state(h).is_failed()) { break; }
+            app.update();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        let levels = app.world().resource::<Assets<Level>>();
+        for handle in &handles {
+            let level = levels.get(handle);
+            assert!(level.is_some_and(|l| l.spawn("player").is_some()), "{:?}: {:?}", handle.path(), server.load_state(handle));
+        }
+    }
+
+    #[test]
+    fn bad_ron_names_the_line_and_column() {
+        let err = load("(\n  width: 3,\n  height: oops,\n)").unwrap_err();
+        let LevelLoadError::Syntax { line, col, .. } = &err else { panic!("{err:?}") };
+        assert_eq!(*line, 3);
+        assert!(*col > 1);
+        assert!(err.to_string().starts_with(&format!("{PATH}:3:{col}: ")), "{err}");
+    }
+
+    #[test]
+    fn unknown_tiles_name_the_row_and_column() {
+        let err = load(r#"(width: 3, height: 2, tiles: ["...", "..x"])"#).unwrap_err();
+        assert!(matches!(err, LevelLoadError::BadTile { row: 1, col: 2, code: 'x', .. }), "{err:?}");
+        assert_eq!(err.to_string(), format!("{PATH}: unknown tile code 'x' at row 1, column 2"));
+    }
+
+    #[test]
+    fn rows_must_be_as_wide_as_the_level() {
+        let err = load(r#"(width: 3, height: 2, tiles: ["...", "...."])"#).unwrap_err();
+        assert!(matches!(err, LevelLoadError::RowWidth { row: 1, expected: 3, found: 4, .. }), "{err:?}");
+        assert_eq!(err.to_string(), format!("{PATH}: row 1 has 4 tiles, expected width 3"));
+    }
+
+    #[test]
+    fn row_count_must_match_the_height() {
+        let err = load(r#"(width: 3, height: 3, tiles: ["...", "..."])"#).unwrap_err();
+        assert!(matches!(err, LevelLoadError::RowCount { expected: 3, found: 2, .. }), "{err:?}");
+        assert_eq!(err.to_string(), format!("{PATH}: height is 3 but `tiles` has 2 rows"));
+    }
+
+    #[test]
+    fn spawns_must_be_on_the_map() {
+        let err = load(r#"(width: 2, height: 1, tiles: [".."], spawns: [(name: "player", x: 2, y: 0)])"#).unwrap_err();
+        assert!(matches!(err, LevelLoadError::SpawnOutOfBounds { x: 2, y: 0, .. }), "{err:?}");
+        assert!(err.to_string().starts_with(PATH), "{err}");
+    }
+}
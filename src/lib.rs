@@ -0,0 +1,76 @@
+//! Shared by the game (`src/main.rs`) and the dedicated server (`src/bin/server.rs`).
+use bevy::prelude::*;
+use bevy_renet::{RenetReceive, RenetSend, RenetServerPlugin};
+use bevy_renet::netcode::NetcodeServerPlugin;
+use bevy_renet::renet::RenetServer;
+use states::AppState;
+
+pub mod grid;
+pub mod constants;
+pub mod camera;
+pub mod collision;
+pub mod world;
+pub mod setup;
+pub mod billboard;
+pub mod nametag;
+pub mod minimap;
+pub mod ping;
+pub mod fog;
+pub mod save;
+pub mod level;
+pub mod tiled;
+pub mod pathfinding;
+pub mod cursor;
+pub mod roster;
+pub mod states;
+pub mod net;
+
+/// The headless part of the game: level loading, collision and the authoritative
+/// server at a fixed tick. No window, renderer, camera or minimap; the game adds
+/// those on top. Expects `net::NetMode` and an `AppState` to have been set up.
+pub fn add_simulation(app: &mut App) -> &mut App {
+    app.insert_resource(world::Blocked::default()) // fill this at load
+        .insert_resource(Time::<Fixed>::from_hz(constants::TICK_HZ))
+        .insert_resource(net::sim::NetSim::from_args())
+        .init_resource::<net::stats::NetStats>()
+        .add_plugins((RenetServerPlugin, NetcodeServerPlugin))
+        .add_message::<level::LevelBuilt>()
+        .init_asset::<level::Level>()
+        .init_asset_loader::<level::LevelLoader>()
+        .init_asset_loader::<tiled::TiledLoader>()
+        // --- Level ---
+        .add_systems(OnEnter(AppState::Loading), level::load_start_level)
+        .add_systems(Update, level::wait_for_level.run_if(in_state(AppState::Loading)))
+        .add_systems(OnEnter(AppState::InGame), (level::spawn_level, net::server::start_server, net::discovery::start_beacon).chain())
+        .add_systems(OnExit(AppState::InGame), (level::unload_level, net::server::stop_server, net::discovery::stop_beacon))
+        .add_systems(Update, level::reload_level.run_if(in_state(AppState::InGame)))
+        // --- Server (see net/mod.rs) ---
+        .add_systems(Update, (
+            net::server::check_versions,
+            net::server::handle_server_events,
+            net::server::receive_reliable,
+            net::server::broadcast_avatars,
+        ).chain()
+            .run_if(resource_exists::<RenetServer>))
+        .add_systems(Update, net::discovery::broadcast_beacon
+            .run_if(resource_exists::<net::discovery::BeaconBroadcaster>.and(resource_exists::<RenetServer>)))
+        .add_systems(PreUpdate, net::sim::pump_relay.before(RenetReceive).run_if(resource_exists::<net::sim::SimRelay>))
+        .add_systems(PostUpdate, net::sim::pump_relay.after(RenetSend).run_if(resource_exists::<net::sim::SimRelay>))
+        .add_systems(PreUpdate, net::platform::mock::server_update.in_set(RenetReceive)
+            .after(RenetServerPlugin::update_system)
+            .before(RenetServerPlugin::emit_server_events_system)
+            .run_if(resource_exists::<net::platform::mock::MockServerTransport>))
+        .add_systems(PostUpdate, net::platform::mock::server_send.in_set(RenetSend)
+            .run_if(resource_exists::<net::platform::mock::MockServerTransport>))
+        .add_systems(FixedUpdate, (
+            net::stats::begin_server_tick,
+            net::server::receive_inputs,
+            net::server::apply_client_inputs,
+            net::server::broadcast_snapshots,
+            net::stats::end_server_tick,
+        ).chain().after(collision::move_with_collision_system).run_if(resource_exists::<RenetServer>));
+    // Renet over Steam's sockets for lobby hosts (see net/platform).
+    #[cfg(feature = "steam")]
+    app.add_plugins(bevy_renet::steam::SteamServerPlugin);
+    app
+}
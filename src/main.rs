@@ -1,29 +1,149 @@
-use bevy::prelude::*;
-mod grid;
-mod constants;
-mod camera;
-mod collision;
-mod world;
-mod setup;
+use bevy::{asset::io::AssetSourceBuilder, prelude::*};
+use bevy_egui::{EguiGlobalSettings, EguiPlugin, EguiPrimaryContextPass};
+use bevy_renet::{RenetClientPlugin, RenetReceive, RenetSend};
+use bevy_renet::netcode::NetcodeClientPlugin;
+use bevy_renet::renet::RenetClient;
+use isometric::{billboard, camera, collision, cursor, fog, grid, level, minimap, nametag, net, pathfinding, ping, roster, save, setup, states, world};
+use states::AppState;
 
 fn main() {
-    App::new()
-        .insert_resource(world::Blocked::default()) // fill this at load
+    let mut app = App::new();
+    app.insert_resource(net::NetMode::from_args())
+        // Portraits live outside `assets/` (see roster.rs).
+        .register_asset_source(roster::PORTRAIT_SOURCE, AssetSourceBuilder::platform_default(roster::PORTRAIT_DIR, None))
         .add_plugins(DefaultPlugins)
-        // .add_systems(Startup, setup)
-        .add_systems(Startup, (setup::scene, setup::minimap))
-        // .add_systems(Startup, spawn_asset.after(setup::scene))
-        .add_systems(Update, grid::draw_grid_gizmos) // draw grid
-        //.add_systems(Update, (collision::move_with_collision_system, collision::sync_render_from_grid))
-        .add_systems(Update, (collision::follow_player, world::light_player, collision::sync_render_from_grid))        
-        .add_systems(Update, (camera::handle_spin_input, (camera::animate_camera_spin, camera::sync_minimap_to_iso_yaw).chain()))        
+        .init_state::<AppState>();
+    // Level, collision and the server side of networking (shared with src/bin/server.rs).
+    isometric::add_simulation(&mut app);
+    if let Some(platform) = net::platform::ActivePlatform::from_args() {
+        app.insert_resource(platform);
+    }
+    #[cfg(feature = "steam")]
+    app.add_plugins(bevy_renet::steam::SteamClientPlugin);
+
+    app.init_resource::<collision::MoveFrame>()
+        .init_resource::<pathfinding::TileCosts>()
+        .init_resource::<pathfinding::ClickToMove>()
+        .init_resource::<cursor::HoveredTile>()
+        .init_resource::<fog::FogOfWar>()
+        .init_resource::<ping::Pings>()
+        .init_resource::<net::client::Reconciliation>()
+        .init_resource::<net::client::LastDisconnect>()
+        .init_resource::<net::interpolation::Interpolation>()
+        .init_resource::<net::interpolation::SnapshotClock>()
+        .init_resource::<net::stats::ServerVisualizer>()
+        .init_resource::<net::stats::ClientVisualizer>()
+        .add_plugins((RenetClientPlugin, NetcodeClientPlugin))
+        .insert_resource(EguiGlobalSettings { auto_create_primary_context: false, ..default() })
+        .add_plugins(EguiPlugin::default())
+        .add_message::<cursor::TileClicked>()
+        .add_message::<cursor::TileRightClicked>()
+        .add_message::<net::platform::LobbyEvent>()
+        .init_resource::<net::platform::LobbyStatus>()
+        .init_resource::<net::discovery::LanBrowser>()
+        // --- Menu / Lobby / Loading ---
+        .add_systems(Startup, roster::load_roster)
+        .add_systems(OnEnter(AppState::Menu), states::menu::spawn_menu)
+        .add_systems(OnEnter(AppState::Lobby), (states::lobby::spawn_lobby, net::discovery::start_lan_browser))
+        .add_systems(OnExit(AppState::Lobby), net::discovery::stop_lan_browser)
+        .add_systems(Update, net::discovery::receive_beacons.run_if(in_state(AppState::Lobby)))
+        .add_systems(EguiPrimaryContextPass, net::discovery::server_browser_panel.run_if(in_state(AppState::Lobby)))
+        .add_systems(OnEnter(AppState::Loading), states::game::spawn_loading_screen)
+        .add_systems(Update, (
+            states::handle_menu_buttons,
+            states::menu::menu_keys.run_if(in_state(AppState::Menu)),
+            states::lobby::lobby_keys.run_if(in_state(AppState::Lobby)),
+            (states::lobby::pick_character, states::lobby::show_chosen_character).chain()
+                .run_if(in_state(AppState::Lobby)),
+        ))
+        // --- In game ---
+        .add_systems(OnEnter(AppState::InGame), (setup::scene, setup::player, setup::minimap).chain()
+            .before(level::spawn_level))
+        .add_systems(OnEnter(AppState::InGame), net::client::start_client.after(net::server::start_server))
+        .add_systems(OnExit(AppState::InGame), net::client::stop_client)
+        .add_systems(PreUpdate, (cursor::update_hovered_tile, cursor::emit_tile_clicks).chain()
+            .after(bevy::input::InputSystems).after(bevy::ui::UiSystems::Focus)
+            .run_if(in_state(AppState::InGame)))
+        .add_systems(Update, (
+            (states::game::leave_game, level::spawn_level_visuals, (setup::player_visuals, billboard::show_portraits).chain()),
+            (grid::draw_grid_gizmos, grid::draw_hovered_tile, ping::draw_pings), // draw grid
+        ).run_if(in_state(AppState::InGame)))
+        .add_systems(Update, minimap::click_minimap.before(pathfinding::click_to_move).run_if(in_state(AppState::InGame)))
+        .add_systems(Update, (
+            (collision::toggle_move_frame, pathfinding::cycle_path_mode),
+            (collision::input_move_grid, collision::follow_player, pathfinding::cancel_path_on_keys, pathfinding::click_to_move).chain(),
+            pathfinding::inspect_tile,
+            world::light_player,
+            collision::sync_render_from_grid,
+        ).chain().run_if(in_state(AppState::InGame)))
+        // --- Fog of war and save games ---
+        .add_systems(Update, (fog::reset_fog, fog::look_around, fog::paint_fog).chain()
+            .after(collision::sync_render_from_grid).run_if(in_state(AppState::InGame)))
+        .add_systems(Update, save::quick_save.run_if(in_state(AppState::InGame)))
+        .add_systems(OnExit(AppState::InGame), save::save_on_exit.before(level::unload_level))
+        // --- Pings (see ping.rs) ---
+        .add_systems(Update, ping::expire_pings.before(minimap::click_minimap).run_if(in_state(AppState::InGame)))
+        .add_systems(OnExit(AppState::InGame), ping::clear_pings)
+        .add_systems(Update, (
+            net::server::share_host_explored.after(fog::look_around),
+            net::server::share_host_pings.after(minimap::click_minimap),
+        ).run_if(resource_exists::<bevy_renet::renet::RenetServer>.and(in_state(AppState::InGame))))
+        .add_systems(FixedUpdate, pathfinding::follow_path
+            .before(collision::move_with_collision_system).before(net::client::predict_and_send)
+            .run_if(in_state(AppState::InGame)))
+        .add_systems(FixedUpdate, collision::move_with_collision_system
+            .run_if(in_state(AppState::InGame).and(net::simulates_locally)))
+        // --- Networking: client side and debug panels (see net/mod.rs) ---
+        .add_systems(Update, net::sim::toggle_sim_panel.run_if(in_state(AppState::InGame)))
+        .add_systems(Update, (net::stats::toggle_net_stats, net::stats::roll_net_stats, net::stats::update_visualizers)
+            .run_if(in_state(AppState::InGame)))
+        .add_systems(OnExit(AppState::InGame), net::stats::reset_net_stats)
+        .add_systems(EguiPrimaryContextPass, (net::sim::sim_panel, net::stats::net_stats_panel)
+            .run_if(in_state(AppState::InGame)))
+        .add_systems(PreUpdate, net::platform::mock::client_update.in_set(RenetReceive)
+            .after(RenetClientPlugin::update_system)
+            .run_if(resource_exists::<net::platform::mock::MockClientTransport>.and(resource_exists::<RenetClient>)))
+        .add_systems(PostUpdate, net::platform::mock::client_send.in_set(RenetSend)
+            .run_if(resource_exists::<net::platform::mock::MockClientTransport>.and(resource_exists::<RenetClient>)))
+        .add_systems(Update, (net::platform::poll_platform, net::platform::follow_lobby_events).chain()
+            .run_if(resource_exists::<net::platform::ActivePlatform>))
+        .add_systems(Update, (net::platform::lobby_platform_keys, net::platform::update_lobby_status)
+            .run_if(resource_exists::<net::platform::ActivePlatform>.and(in_state(AppState::Lobby))))
+        .add_systems(OnExit(AppState::InGame), net::platform::leave_lobby
+            .run_if(resource_exists::<net::platform::ActivePlatform>))
+        .add_systems(FixedUpdate, net::client::predict_and_send.run_if(bevy_renet::client_connected))
+        .add_systems(Update, (
+            net::client::receive_reliable,
+            net::client::leave_on_disconnect,
+            net::client::send_avatar.run_if(bevy_renet::client_connected),
+            net::client::apply_snapshots,
+            net::client::tag_avatars,
+            net::client::send_explored.run_if(bevy_renet::client_connected),
+            net::client::send_pings.run_if(bevy_renet::client_connected),
+        ).chain().before(collision::sync_render_from_grid)
+            .run_if(resource_exists::<RenetClient>.and(in_state(AppState::InGame))))
+        .add_systems(Update, (
+            net::client::smooth_prediction_error,
+            net::interpolation::interpolate_remote_players,
+            net::interpolation::toggle_interpolation_debug,
+            net::interpolation::draw_interpolation_debug,
+        ).chain().after(collision::sync_render_from_grid)
+            .run_if(resource_exists::<RenetClient>.and(in_state(AppState::InGame))))
+        .add_systems(Update, (
+            camera::handle_spin_input,
+            camera::animate_camera_spin,
+            camera::snap_follow_to_player,
+            camera::follow_center_smooth,
+            camera::sync_minimap_to_iso_yaw,
+            billboard::face_iso_camera,
+        ).chain().after(collision::sync_render_from_grid).run_if(in_state(AppState::InGame)))
+        .add_systems(Update, (
+            (nametag::spawn_name_tags, nametag::place_name_tags).chain(),
+            (minimap::spawn_minimap_icons, minimap::place_minimap_icons).chain(),
+            ping::place_ping_markers,
+        ).after(camera::sync_minimap_to_iso_yaw).after(net::interpolation::interpolate_remote_players)
+            .after(net::client::smooth_prediction_error).run_if(in_state(AppState::InGame)))
+        .add_systems(Update, (camera::handle_zoom_input, camera::animate_zoom, camera::sync_minimap_zoom).chain()
+            .run_if(in_state(AppState::InGame)))
         .run();
 }
-
-// --- Separate system to load your Blender asset (.glb) ---
-fn spawn_asset(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands.spawn((        
-        SceneRoot(asset_server.load("models/resource.glb#Scene0")),
-        Transform::from_xyz(1.0, 1.0, 1.0),
-    ));
-}
\ No newline at end of file
@@ -0,0 +1,283 @@
+//! Player icons over the minimap (`setup::minimap`): each player's portrait at
+//! their spot on the map, ours bigger and framed in gold. Players beyond the
+//! map's edge stay pinned to it with an arrow pointing their way. Other teams'
+//! players only show while they stand where we can see (see `fog`).
+//!
+//! The minimap can be clicked, too: a left click walks there and an
+//! alt-click pings the tile for our team (see `ping`).
+use bevy::{prelude::*, ui::RelativeCursorPosition};
+use crate::camera::MinimapCamera;
+use crate::constants::{TILE_H, TILE_W};
+use crate::cursor;
+use crate::fog::{FogOfWar, TileFog};
+use crate::ping::Pings;
+use crate::roster::{Avatar, Roster};
+use crate::world;
+
+/// Side of the minimap on screen, in UI pixels.
+pub const UI_SIZE: f32 = 180.0;
+const ICON_SIZE: f32 = 16.0;
+const LOCAL_ICON_SIZE: f32 = 22.0;
+const ARROW_SIZE: f32 = 6.0;
+// Same colours as the players' discs (see `setup::player_visuals`).
+const ICON_BORDER: Color = Color::srgb(0.4, 0.6, 0.9);
+const LOCAL_ICON_BORDER: Color = Color::srgb(0.95, 0.8, 0.3);
+
+/// The minimap's UI node; icons are its children.
+#[derive(Component, Debug)]
+pub struct MinimapFrame;
+
+/// `player`'s icon on the minimap.
+#[derive(Component, Debug)]
+pub struct MinimapIcon {
+    pub player: Entity,
+}
+
+/// Shown next to an icon pinned to the edge, pointing at where the player is.
+#[derive(Component, Debug)]
+pub struct MinimapArrow;
+
+/// Where `world` shows on the minimap, in UI pixels from its top-left corner
+/// (possibly outside it).
+pub fn world_to_minimap(cam: &Camera, cam_t: &Transform, world: Vec3) -> Option<Vec2> {
+    let px = cam.world_to_viewport(&GlobalTransform::from(*cam_t), world).ok()?;
+    Some(px / cam.logical_viewport_size()? * UI_SIZE)
+}
+
+/// Where the ground under `ui` (UI pixels from the minimap's top-left corner)
+/// is: the inverse of [`world_to_minimap`]. Worked out from the minimap
+/// camera's transform, whose up vector `camera::sync_minimap_to_iso_yaw` turns
+/// with the iso camera, and the size of its view in world units.
+pub fn minimap_to_ground(cam_t: &Transform, view_size: Vec2, ui: Vec2) -> Vec3 {
+    let off = (ui / UI_SIZE - 0.5) * view_size;
+    // UI y grows downward, toward the camera's -up.
+    let mut ground = cam_t.translation + cam_t.right() * off.x - cam_t.up() * off.y;
+    ground.y = 0.0;
+    ground
+}
+
+/// Pulls `at` inside the minimap, at least `inset` from each edge. Also says
+/// which way it was pulled from (zero if it was inside already).
+pub fn clamp_to_minimap(at: Vec2, inset: f32) -> (Vec2, Vec2) {
+    let clamped = at.clamp(Vec2::splat(inset), Vec2::splat(UI_SIZE - inset));
+    (clamped, (at - clamped).normalize_or_zero())
+}
+
+/// Whether a player standing on `cell` gets an icon: always if it is us
+/// (`local`) or a teammate (`team` is `our_team`), otherwise only while the
+/// fog says we can see the tile.
+pub fn icon_shown(
+    fog: &FogOfWar,
+    cell: (i32, i32),
+    local: bool,
+    team: Option<&world::Team>,
+    our_team: Option<&world::Team>,
+) -> bool {
+    local || (team.is_some() && team == our_team) || fog.tile(cell) == TileFog::Visible
+}
+
+fn icon_size(local: bool) -> f32 {
+    if local { LOCAL_ICON_SIZE } else { ICON_SIZE }
+}
+
+/// Gives each player an icon (ours drawn last, on top) and drops the icons of
+/// players that are gone.
+pub fn spawn_minimap_icons(
+    mut commands: Commands,
+    frame: Query<Entity, With<MinimapFrame>>,
+    players: Query<(Entity, Has<world::LocalPlayer>), With<world::GridPos>>,
+    icons: Query<(Entity, &MinimapIcon)>,
+) {
+    let Ok(frame) = frame.single() else { return };
+    for (e, icon) in &icons {
+        if !players.contains(icon.player) {
+            commands.entity(e).despawn();
+        }
+    }
+    for (player, local) in &players {
+        if icons.iter().any(|(_, icon)| icon.player == player) { continue; }
+        let size = icon_size(local);
+        commands.entity(frame).with_child((
+            MinimapIcon { player },
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Px(size),
+                height: Val::Px(size),
+                border: UiRect::all(Val::Px(2.0)),
+                ..default()
+            },
+            BorderColor::all(if local { LOCAL_ICON_BORDER } else { ICON_BORDER }),
+            // A plain square until the portrait is known.
+            ImageNode::solid_color(if local { LOCAL_ICON_BORDER } else { ICON_BORDER }),
+            ZIndex(if local { 2 } else { 1 }),
+            // Placed (and shown) by `place_minimap_icons`.
+            Visibility::Hidden,
+            children![(
+                MinimapArrow,
+                Node {
+                    position_type: PositionType::Absolute,
+                    width: Val::Px(ARROW_SIZE),
+                    height: Val::Px(ARROW_SIZE),
+                    ..default()
+                },
+                BackgroundColor(if local { LOCAL_ICON_BORDER } else { ICON_BORDER }),
+                UiTransform::from_rotation(Rot2::degrees(45.0)),
+                Visibility::Hidden,
+            )],
+        ));
+    }
+}
+
+/// What `place_minimap_icons` needs to know about a player.
+type IconSource = (
+    &'static Transform,
+    &'static world::GridPos,
+    Option<&'static Avatar>,
+    Option<&'static world::Team>,
+    Has<world::LocalPlayer>,
+);
+/// The arrows under the icons (which are `Node`s too).
+type ArrowFilter = (With<MinimapArrow>, Without<MinimapIcon>);
+
+/// Puts each icon where its player is on the minimap (pinned to the edge if
+/// off it), showing their portrait once it is known, and hides those the fog
+/// should (see [`icon_shown`]).
+pub fn place_minimap_icons(
+    roster: Option<Res<Roster>>,
+    fog: Res<FogOfWar>,
+    local_team: Query<Option<&world::Team>, With<world::LocalPlayer>>,
+    cam_q: Query<(&Camera, &Transform), With<MinimapCamera>>,
+    players: Query<IconSource, Without<MinimapCamera>>,
+    mut icons: Query<(&MinimapIcon, &Children, &mut Node, &mut ImageNode, &mut Visibility)>,
+    mut arrows: Query<(&mut Node, &mut Visibility), ArrowFilter>,
+) {
+    let Ok((cam, cam_t)) = cam_q.single() else { return };
+    let our_team = local_team.single().ok().flatten();
+    for (icon, children, mut node, mut image, mut visibility) in &mut icons {
+        let Ok((t, gp, avatar, team, local)) = players.get(icon.player) else { continue };
+        if !icon_shown(&fog, world::cell_at(gp.x, gp.y), local, team, our_team) {
+            visibility.set_if_neq(Visibility::Hidden);
+            continue;
+        }
+        let Some(at) = world_to_minimap(cam, cam_t, t.translation) else { continue };
+
+        let size = icon_size(local);
+        let (at, outward) = clamp_to_minimap(at, size / 2.0);
+        node.left = Val::Px(at.x - size / 2.0);
+        node.top = Val::Px(at.y - size / 2.0);
+        visibility.set_if_neq(Visibility::Inherited);
+
+        let portrait = avatar.and_then(|a| roster.as_ref()?.portrait(a));
+        if let Some(portrait) = portrait.filter(|p| p.image != image.image) {
+            image.image = portrait.image.clone();
+            image.color = Color::WHITE;
+        }
+
+        let mut arrows = arrows.iter_many_mut(children);
+        while let Some((mut arrow, mut arrow_visibility)) = arrows.fetch_next() {
+            if outward == Vec2::ZERO {
+                arrow_visibility.set_if_neq(Visibility::Hidden);
+                continue;
+            }
+            // Just outside the icon (whose content box starts inside its border).
+            let tip = outward * (size / 2.0 + ARROW_SIZE / 2.0) + Vec2::splat(size / 2.0 - 2.0 - ARROW_SIZE / 2.0);
+            arrow.left = Val::Px(tip.x);
+            arrow.top = Val::Px(tip.y);
+            arrow_visibility.set_if_neq(Visibility::Inherited);
+        }
+    }
+}
+
+/// Clicks on the minimap: a left click walks to the tile (as a
+/// `cursor::TileClicked`, see `pathfinding::click_to_move`), an alt-click
+/// pings it, for our team too if we have one. `cursor::update_hovered_tile`
+/// ignores the world under the minimap, so these are the only clicks.
+pub fn click_minimap(
+    buttons: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    frame: Query<&RelativeCursorPosition, With<MinimapFrame>>,
+    cam_q: Query<(&Transform, &Projection), With<MinimapCamera>>,
+    local: Query<Has<world::Team>, With<world::LocalPlayer>>,
+    mut pings: ResMut<Pings>,
+    mut clicked: MessageWriter<cursor::TileClicked>,
+) {
+    if !buttons.just_pressed(MouseButton::Left) { return; }
+    let Ok(cursor) = frame.single() else { return };
+    let Some(normalized) = cursor.normalized.filter(|_| cursor.cursor_over()) else { return };
+    let Ok((cam_t, Projection::Orthographic(ortho))) = cam_q.single() else { return };
+
+    // `normalized` is -0.5..0.5 across the frame, from its centre.
+    let ground = minimap_to_ground(cam_t, ortho.area.size(), (normalized + 0.5) * UI_SIZE);
+    let g = world::grid_from_iso_world(ground.x, ground.z, TILE_W, TILE_H);
+    let cell = world::cell_at(g.x, g.y);
+    if keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]) {
+        pings.place(cell, local.single().unwrap_or(false));
+    } else {
+        clicked.write(cursor::TileClicked { cell });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The minimap camera as `camera::sync_minimap_to_iso_yaw` places it.
+    fn minimap_camera(at: Vec3, iso_yaw_deg: f32) -> Transform {
+        let up = Quat::from_rotation_y((iso_yaw_deg - 45.0).to_radians()) * -Vec3::Z;
+        Transform::from_xyz(at.x, 50.0, at.z).looking_at(Vec3::new(at.x, 0.0, at.z), up)
+    }
+
+    #[test]
+    fn clicks_follow_the_minimap_rotation() {
+        let view = Vec2::splat(20.0);
+        let centre = Vec2::splat(UI_SIZE / 2.0);
+        let top = Vec2::new(UI_SIZE / 2.0, 0.0);
+        let right = Vec2::new(UI_SIZE, UI_SIZE / 2.0);
+
+        let cam_t = minimap_camera(Vec3::new(7.0, 0.0, -3.0), 45.0);
+        assert!(minimap_to_ground(&cam_t, view, centre).abs_diff_eq(Vec3::new(7.0, 0.0, -3.0), 1e-4));
+        // Unturned: up is -z (grid +y), right is +x.
+        assert!(minimap_to_ground(&cam_t, view, top).abs_diff_eq(Vec3::new(7.0, 0.0, -13.0), 1e-4));
+        assert!(minimap_to_ground(&cam_t, view, right).abs_diff_eq(Vec3::new(17.0, 0.0, -3.0), 1e-4));
+
+        // After one spin step the map has turned a quarter: up is now -x.
+        let cam_t = minimap_camera(Vec3::ZERO, 135.0);
+        assert!(minimap_to_ground(&cam_t, view, top).abs_diff_eq(Vec3::new(-10.0, 0.0, 0.0), 1e-4));
+        assert!(minimap_to_ground(&cam_t, view, right).abs_diff_eq(Vec3::new(0.0, 0.0, -10.0), 1e-4));
+    }
+
+    #[test]
+    fn off_map_positions_pin_to_the_edge() {
+        let inside = Vec2::new(40.0, 120.0);
+        assert_eq!(clamp_to_minimap(inside, 8.0), (inside, Vec2::ZERO));
+
+        let (at, outward) = clamp_to_minimap(Vec2::new(UI_SIZE + 50.0, 90.0), 8.0);
+        assert_eq!(at, Vec2::new(UI_SIZE - 8.0, 90.0));
+        assert_eq!(outward, Vec2::X);
+
+        // Past a corner: pinned in the corner, pointing diagonally out.
+        let (at, outward) = clamp_to_minimap(Vec2::new(-100.0, -100.0), 8.0);
+        assert_eq!(at, Vec2::splat(8.0));
+        assert!(outward.abs_diff_eq(Vec2::new(-1.0, -1.0).normalize(), 1e-6));
+    }
+
+    #[test]
+    fn enemies_in_the_fog_get_no_icon() {
+        let mut fog = FogOfWar::new(20, 20);
+        fog.look_from(&world::Blocked::default(), (2, 2));
+        fog.look_from(&world::Blocked::default(), (15, 15));
+        let (red, blue) = (world::Team(0), world::Team(1));
+        let explored = (2, 2);
+        let unexplored = (2, 15);
+        let in_view = (14, 15);
+
+        for cell in [explored, unexplored] {
+            assert!(!icon_shown(&fog, cell, false, Some(&blue), Some(&red)), "enemy on {cell:?}");
+            assert!(!icon_shown(&fog, cell, false, None, Some(&red)), "teamless player on {cell:?}");
+            assert!(!icon_shown(&fog, cell, false, None, None), "nobody is our teammate without a team");
+            assert!(icon_shown(&fog, cell, false, Some(&red), Some(&red)), "teammate on {cell:?}");
+            assert!(icon_shown(&fog, cell, true, Some(&red), Some(&red)), "us on {cell:?}");
+        }
+        assert!(icon_shown(&fog, in_view, false, Some(&blue), Some(&red)));
+    }
+}
@@ -0,0 +1,72 @@
+//! Names floating over players' heads: a UI text per player with an
+//! `Avatar`, moved every frame to where the player's head is on screen.
+use bevy::prelude::*;
+use crate::camera::IsoCamera;
+use crate::roster::Avatar;
+use crate::states::AppState;
+use crate::world;
+
+/// How far above the player's transform (its centre) the tag sits.
+const HEAD_HEIGHT: f32 = 0.65;
+const TAG_COLOR: Color = Color::srgb(0.95, 0.95, 0.95);
+const LOCAL_TAG_COLOR: Color = Color::srgb(0.95, 0.8, 0.3);
+
+/// The name tag of `player`.
+#[derive(Component, Debug)]
+pub struct NameTag {
+    pub player: Entity,
+}
+
+/// Gives players a tag once their avatar is known, and renames it when it changes.
+pub fn spawn_name_tags(
+    mut commands: Commands,
+    players: Query<(Entity, &Avatar, Has<world::LocalPlayer>), Changed<Avatar>>,
+    mut tags: Query<(&NameTag, &mut Text)>,
+) {
+    for (player, avatar, local) in &players {
+        let name = avatar.to_string();
+        if let Some((_, mut text)) = tags.iter_mut().find(|(tag, _)| tag.player == player) {
+            text.0 = name;
+            continue;
+        }
+        commands.spawn((
+            DespawnOnExit(AppState::InGame),
+            NameTag { player },
+            Text::new(name),
+            TextFont { font_size: 14.0, ..default() },
+            TextColor(if local { LOCAL_TAG_COLOR } else { TAG_COLOR }),
+            TextShadow::default(),
+            Node { position_type: PositionType::Absolute, ..default() },
+            // Placed (and shown) by `place_name_tags`.
+            Visibility::Hidden,
+        ));
+    }
+}
+
+/// Centres each tag over its player's head as seen by the `IsoCamera`, after
+/// the camera has moved this frame. Tags of players that are gone go too.
+pub fn place_name_tags(
+    mut commands: Commands,
+    cam_q: Query<(&Camera, &Transform), With<IsoCamera>>,
+    players: Query<&Transform, Without<IsoCamera>>,
+    mut tags: Query<(Entity, &NameTag, &ComputedNode, &mut Node, &mut Visibility)>,
+) {
+    let Ok((cam, cam_t)) = cam_q.single() else { return };
+    // The camera's `GlobalTransform` is a frame behind during spins; its `Transform` is current.
+    let cam_gt = GlobalTransform::from(*cam_t);
+    for (e, tag, computed, mut node, mut visibility) in &mut tags {
+        let Ok(player) = players.get(tag.player) else {
+            commands.entity(e).despawn();
+            continue;
+        };
+        let head = player.translation + Vec3::Y * HEAD_HEIGHT;
+        let Ok(at) = cam.world_to_viewport(&cam_gt, head) else {
+            visibility.set_if_neq(Visibility::Hidden);
+            continue;
+        };
+        let size = computed.size() * computed.inverse_scale_factor();
+        node.left = Val::Px(at.x - size.x / 2.0);
+        node.top = Val::Px(at.y - size.y);
+        visibility.set_if_neq(Visibility::Inherited);
+    }
+}
@@ -0,0 +1,438 @@
+use bevy::prelude::*;
+use bevy_renet::netcode::{ClientAuthentication, NetcodeClientTransport};
+use bevy_renet::renet::{DefaultChannel, RenetClient};
+use std::collections::{HashMap, VecDeque};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use crate::collision;
+use crate::fog::{self, FogOfWar};
+use crate::ping::Pings;
+use crate::roster::Avatar;
+use crate::states::AppState;
+use crate::world;
+use super::snapshot::{self, SnapshotHistory, WorldState};
+use super::{interpolation, platform, stats};
+use super::protocol::{self, ClientMessage, InputCmd, PlayerState, ServerMessage};
+use super::{NetMode, NetPlayer};
+
+/// Inputs older than this many ticks are dropped from the history even if
+/// unacknowledged (about two seconds at 60 Hz); the next snapshot snaps us back.
+const MAX_HISTORY: usize = 120;
+
+/// Per-connection client bookkeeping.
+#[derive(Resource, Debug, Default)]
+pub struct ClientSession {
+    pub next_seq: u32,
+    /// Inputs the server has not acknowledged yet, oldest first. The newest few
+    /// are resent with every packet; all of them are replayed on reconciliation.
+    pub history: VecDeque<InputCmd>,
+    /// Newest snapshot tick applied (and acknowledged with our inputs).
+    pub last_tick: u32,
+    /// What recent snapshots described, for the server's deltas to build on.
+    pub snapshots: SnapshotHistory,
+    /// Whether the server has been told who we play as.
+    pub avatar_sent: bool,
+    /// Who everyone else is, as the server told us; players come and go with
+    /// our interest area, their avatars stay here.
+    pub avatars: HashMap<u64, Avatar>,
+    /// Their teams, likewise (players without one aren't here).
+    pub teams: HashMap<u64, world::Team>,
+}
+
+/// How corrections from the server are shown.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct Reconciliation {
+    /// How fast a correction's visual offset fades out, per second (0 = show the pop).
+    pub blend_rate: f32,
+    /// Corrections bigger than this (tiles) snap instead of blending.
+    pub snap_distance: f32,
+}
+
+impl Default for Reconciliation {
+    fn default() -> Self {
+        Self { blend_rate: 12.0, snap_distance: 2.0 }
+    }
+}
+
+/// Why the last session ended, for the menu to show once.
+#[derive(Resource, Debug, Default)]
+pub struct LastDisconnect(pub Option<String>);
+
+/// Render-only offset left over from the last correction, in grid units.
+/// The simulation uses the corrected `GridPos`; only the mesh lags behind.
+#[derive(Component, Debug, Default)]
+pub struct PredictionError(pub Vec2);
+
+/// `OnEnter(AppState::InGame)` when joining: connects to the server (directly
+/// or through the platform) and tags the local player with our client id.
+pub fn start_client(
+    mut commands: Commands,
+    mode: Res<NetMode>,
+    platform: Option<Res<platform::ActivePlatform>>,
+    local: Query<Entity, With<world::LocalPlayer>>,
+) {
+    let client_id = match *mode {
+        NetMode::Client { server } => {
+            let Some(client_id) = connect_udp(&mut commands, server) else { return };
+            info!("connecting to {server} as {client_id}");
+            client_id
+        }
+        NetMode::LobbyClient { owner } => {
+            let Some(platform) = platform else {
+                error!("no platform to reach the lobby owner through");
+                return;
+            };
+            // The platform's transports use platform user ids as client ids.
+            let client_id = platform.0.local_user();
+            info!("connecting to {} as {}", platform.0.user_name(owner), platform.0.user_name(client_id));
+            client_id
+        }
+        _ => return,
+    };
+
+    commands.insert_resource(RenetClient::new(super::connection_config()));
+    if let NetMode::LobbyClient { owner } = *mode {
+        // After the `RenetClient`, which it takes away again if it fails.
+        commands.queue(platform::connect_lobby(owner));
+    }
+    commands.insert_resource(ClientSession { next_seq: 1, ..default() });
+    for e in &local {
+        commands.entity(e).insert((NetPlayer { id: client_id }, PredictionError::default()));
+    }
+}
+
+/// Netcode transport to `server`; returns our client id.
+fn connect_udp(commands: &mut Commands, server: SocketAddr) -> Option<u64> {
+    let socket = match UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))) {
+        Ok(socket) => socket,
+        Err(err) => {
+            error!("can't open a client socket: {err}");
+            return None;
+        }
+    };
+    let now = super::now();
+    // Any id nobody else picks will do; netcode trusts it in unsecure mode.
+    let client_id = (now.as_nanos() as u64).max(super::HOST_ID + 1);
+    let auth = ClientAuthentication::Unsecure {
+        protocol_id: protocol::PROTOCOL_ID,
+        client_id,
+        server_addr: server,
+        user_data: Some(protocol::handshake_user_data()),
+    };
+    let transport = match NetcodeClientTransport::new(now, auth, socket) {
+        Ok(transport) => transport,
+        Err(err) => {
+            error!("can't connect to {server}: {err}");
+            return None;
+        }
+    };
+    commands.insert_resource(transport);
+    Some(client_id)
+}
+
+/// `OnExit(AppState::InGame)`: says goodbye and drops the connection.
+pub fn stop_client(mut commands: Commands, transport: Option<ResMut<NetcodeClientTransport>>) {
+    if let Some(mut transport) = transport {
+        transport.disconnect();
+    }
+    commands.queue(platform::close_transports);
+    commands.remove_resource::<RenetClient>();
+    commands.remove_resource::<NetcodeClientTransport>();
+    commands.remove_resource::<ClientSession>();
+    commands.insert_resource(interpolation::SnapshotClock::default());
+}
+
+/// Handles the server's reliable messages: who other players are, what our
+/// teammates explored or pinged, and why it is turning us away (back to the menu,
+/// which says why).
+pub fn receive_reliable(
+    mut client: ResMut<RenetClient>,
+    mut session: ResMut<ClientSession>,
+    mut fog: Option<ResMut<FogOfWar>>,
+    mut pings: Option<ResMut<Pings>>,
+    mut stats: ResMut<stats::NetStats>,
+    mut last: ResMut<LastDisconnect>,
+    mut next: ResMut<NextState<AppState>>,
+) {
+    while let Some(bytes) = client.receive_message(DefaultChannel::ReliableOrdered) {
+        stats.received(DefaultChannel::ReliableOrdered, bytes.len());
+        match protocol::decode(&bytes) {
+            Some(ServerMessage::Avatar { id, avatar, team }) => {
+                session.avatars.insert(id, avatar);
+                match team {
+                    Some(t) => session.teams.insert(id, world::Team(t)),
+                    None => session.teams.remove(&id),
+                };
+            }
+            Some(ServerMessage::Explored(cells)) => {
+                if let Some(fog) = fog.as_mut() {
+                    fog.explore(fog::cells_from_wire(&cells));
+                }
+            }
+            Some(ServerMessage::Ping([x, y])) => {
+                if let Some(pings) = pings.as_mut() {
+                    pings.add((x as i32, y as i32), false);
+                }
+            }
+            Some(ServerMessage::Rejected(reason)) => {
+                warn!("rejected: {reason}");
+                last.0 = Some(reason.to_string());
+                next.set(AppState::Menu);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Back to the menu if the server goes away; the menu says why.
+pub fn leave_on_disconnect(
+    client: Res<RenetClient>,
+    mut last: ResMut<LastDisconnect>,
+    mut next: ResMut<NextState<AppState>>,
+) {
+    if let Some(reason) = client.disconnect_reason() {
+        warn!("disconnected: {reason}");
+        last.0.get_or_insert_with(|| format!("disconnected: {reason}"));
+        next.set(AppState::Menu);
+    }
+}
+
+/// Once connected: tells the server who we play as, and on which team.
+pub fn send_avatar(
+    mut client: ResMut<RenetClient>,
+    mut session: ResMut<ClientSession>,
+    mut stats: ResMut<stats::NetStats>,
+    local: Query<(&Avatar, Option<&world::Team>), With<world::LocalPlayer>>,
+) {
+    if session.avatar_sent { return; }
+    let Ok((avatar, team)) = local.single() else { return };
+    let mut msgs = vec![ClientMessage::Avatar(avatar.clone())];
+    msgs.extend(team.map(|t| ClientMessage::JoinTeam(t.0)));
+    for msg in msgs {
+        let bytes = protocol::encode(&msg);
+        stats.sent(DefaultChannel::ReliableOrdered, bytes.len());
+        client.send_message(DefaultChannel::ReliableOrdered, bytes);
+    }
+    session.avatar_sent = true;
+}
+
+/// Passes the tiles we explored on to the server, for our teammates.
+pub fn send_explored(
+    mut client: ResMut<RenetClient>,
+    mut stats: ResMut<stats::NetStats>,
+    mut fog: ResMut<FogOfWar>,
+) {
+    if fog.unshared.is_empty() { return; }
+    let cells = fog::cells_to_wire(fog.unshared.drain(..));
+    let bytes = protocol::encode(&ClientMessage::Explored(cells));
+    stats.sent(DefaultChannel::ReliableOrdered, bytes.len());
+    client.send_message(DefaultChannel::ReliableOrdered, bytes);
+}
+
+/// Passes the tiles we pinged on to the server, for our teammates.
+pub fn send_pings(
+    mut client: ResMut<RenetClient>,
+    mut stats: ResMut<stats::NetStats>,
+    mut pings: ResMut<Pings>,
+) {
+    for (x, y) in pings.unshared.drain(..) {
+        let bytes = protocol::encode(&ClientMessage::Ping([x as i16, y as i16]));
+        stats.sent(DefaultChannel::ReliableOrdered, bytes.len());
+        client.send_message(DefaultChannel::ReliableOrdered, bytes);
+    }
+}
+
+/// What `tag_avatars` looks at on a remote player.
+type Tagged = (Entity, &'static NetPlayer, Option<&'static Avatar>, Option<&'static world::Team>);
+
+/// Gives remote players the avatar (and team) the server told us about,
+/// including ones that just came (back) into view.
+pub fn tag_avatars(
+    mut commands: Commands,
+    session: Res<ClientSession>,
+    players: Query<Tagged, Without<world::LocalPlayer>>,
+) {
+    for (e, p, current, team) in &players {
+        let Some(avatar) = session.avatars.get(&p.id) else { continue };
+        if current != Some(avatar) {
+            commands.entity(e).insert(avatar.clone());
+        }
+        match session.teams.get(&p.id) {
+            Some(t) if team != Some(t) => { commands.entity(e).insert(*t); }
+            None if team.is_some() => { commands.entity(e).remove::<world::Team>(); }
+            _ => {}
+        }
+    }
+}
+
+/// Fixed tick: numbers this tick's `MoveIntent`, applies it to the local player
+/// straight away (prediction), and sends it along with a few older ones in case
+/// packets get lost.
+pub fn predict_and_send(
+    time: Res<Time>,
+    blocked: Res<world::Blocked>,
+    mut client: ResMut<RenetClient>,
+    mut session: ResMut<ClientSession>,
+    mut stats: ResMut<stats::NetStats>,
+    mut player_q: Query<(&mut world::GridPos, &mut collision::MoveIntent), With<world::LocalPlayer>>,
+) {
+    let Ok((mut gp, mut intent)) = player_q.single_mut() else { return };
+
+    let cmd = InputCmd {
+        seq: session.next_seq,
+        vel: intent.vel.to_array(),
+        step: intent.step.to_array(),
+    };
+    session.next_seq += 1;
+    collision::simulate_move(&blocked, &mut gp, &cmd.intent(), time.delta_secs());
+    intent.step = IVec2::ZERO;
+
+    session.history.push_back(cmd);
+    while session.history.len() > MAX_HISTORY {
+        session.history.pop_front();
+    }
+    let resend = session.history.len().saturating_sub(super::INPUT_REDUNDANCY);
+    let msg = ClientMessage::Inputs {
+        snapshot_ack: session.last_tick,
+        cmds: session.history.range(resend..).copied().collect(),
+    };
+    let bytes = protocol::encode(&msg);
+    stats.sent(DefaultChannel::Unreliable, bytes.len());
+    client.send_message(DefaultChannel::Unreliable, bytes);
+}
+
+/// Everything `apply_snapshots` may update on a networked player.
+type SnapshotTarget = (
+    Entity,
+    &'static NetPlayer,
+    &'static mut world::GridPos,
+    Option<&'static mut PredictionError>,
+    Option<&'static mut interpolation::SnapshotBuffer>,
+);
+
+/// Applies fresh snapshots: rebuilds each from its delta, buffers them for
+/// remote player interpolation (spawning and despawning remote players as they
+/// come into and leave our area) and reconciles our own player against the
+/// newest one.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_snapshots(
+    mut commands: Commands,
+    mut client: ResMut<RenetClient>,
+    real: Res<Time<Real>>,
+    fixed: Res<Time<Fixed>>,
+    blocked: Res<world::Blocked>,
+    settings: Res<Reconciliation>,
+    mut clock: ResMut<interpolation::SnapshotClock>,
+    mut session: ResMut<ClientSession>,
+    mut stats: ResMut<stats::NetStats>,
+    mut players: Query<SnapshotTarget>,
+) {
+    let mut fresh = Vec::new();
+    while let Some(bytes) = client.receive_message(DefaultChannel::Unreliable) {
+        stats.received(DefaultChannel::Unreliable, bytes.len());
+        stats.snapshot(bytes.len());
+        let Some(msg @ ServerMessage::Snapshot { tick, .. }) = protocol::decode(&bytes) else { continue };
+        // Unreliable means out of order too; anything older than what we applied is stale.
+        if tick > session.last_tick {
+            clock.observe(tick, real.elapsed_secs_f64());
+            fresh.push(msg);
+        }
+    }
+    fresh.sort_by_key(|msg| match msg {
+        ServerMessage::Snapshot { tick, .. } => *tick,
+        _ => 0,
+    });
+
+    // Whole states again, oldest first.
+    let nothing = WorldState::new();
+    let mut states: Vec<(u32, u32, WorldState)> = Vec::new();
+    for msg in fresh {
+        let ServerMessage::Snapshot { tick, baseline, ack, players, removed } = msg else { continue };
+        let base = if baseline == 0 { Some(&nothing) } else { session.snapshots.get(baseline) };
+        // We no longer have what it builds on; the server will fall back to a full one.
+        let Some(base) = base else { continue };
+        let state = snapshot::apply(base, &players, &removed);
+        session.snapshots.push(tick, state.clone());
+        states.push((tick, ack, state));
+    }
+    let Some((tick, ack, mut by_id)) = states.pop() else { return };
+    session.last_tick = tick;
+
+    // Every remote position we heard about this frame, oldest first.
+    let mut samples: HashMap<u64, Vec<(u32, Vec2)>> = HashMap::new();
+    for (t, _, older) in &states {
+        for s in older.values() {
+            samples.entry(s.id).or_default().push((*t, Vec2::new(s.x, s.y)));
+        }
+    }
+
+    for (e, p, mut gp, error, buffer) in &mut players {
+        let state = by_id.remove(&p.id);
+        match (state, error) {
+            // Our own player: predicted, so reconcile rather than overwrite.
+            (Some(s), Some(mut error)) => {
+                let before = Vec2::new(gp.x, gp.y);
+                *gp = reconcile(&blocked, &mut session.history, s, ack, fixed.timestep().as_secs_f32());
+                let correction = before - Vec2::new(gp.x, gp.y);
+                error.0 += correction;
+                if settings.blend_rate <= 0.0 || error.0.length() > settings.snap_distance {
+                    error.0 = Vec2::ZERO;
+                }
+            }
+            (Some(s), None) => {
+                *gp = world::GridPos { x: s.x, y: s.y };
+                if let Some(mut buffer) = buffer {
+                    for &(t, pos) in samples.get(&p.id).into_iter().flatten() {
+                        buffer.push(t, pos);
+                    }
+                    buffer.push(tick, Vec2::new(s.x, s.y));
+                }
+            }
+            (None, None) => { commands.entity(e).despawn(); }
+            (None, Some(_)) => {}
+        }
+    }
+    for s in by_id.into_values() {
+        let gp = world::GridPos { x: s.x, y: s.y };
+        let mut buffer = interpolation::SnapshotBuffer::default();
+        buffer.push(tick, Vec2::new(s.x, s.y));
+        let e = super::spawn_remote_player(&mut commands, s.id, gp);
+        commands.entity(e).insert(buffer);
+    }
+}
+
+/// Rewinds to the server's state as of input `ack` and replays every input
+/// the server had not applied yet. Returns where we should be now.
+fn reconcile(
+    blocked: &world::Blocked,
+    history: &mut VecDeque<InputCmd>,
+    server: PlayerState,
+    ack: u32,
+    dt: f32,
+) -> world::GridPos {
+    while history.front().is_some_and(|cmd| cmd.seq <= ack) {
+        history.pop_front();
+    }
+    let mut gp = world::GridPos { x: server.x, y: server.y };
+    for cmd in history.iter() {
+        collision::simulate_move(blocked, &mut gp, &cmd.intent(), dt);
+    }
+    gp
+}
+
+/// Fades the local player's `PredictionError` and draws the mesh that far
+/// behind its corrected position, so small corrections glide instead of pop.
+pub fn smooth_prediction_error(
+    time: Res<Time>,
+    settings: Res<Reconciliation>,
+    mut player_q: Query<(&mut PredictionError, &mut Transform), With<world::LocalPlayer>>,
+) {
+    for (mut error, mut t) in &mut player_q {
+        error.0 *= (-settings.blend_rate * time.delta_secs()).exp();
+        if error.0.length_squared() < 1e-6 {
+            error.0 = Vec2::ZERO;
+        }
+        // Grid y is world -z (see `world::grid_to_iso`).
+        t.translation.x += error.0.x;
+        t.translation.z -= error.0.y;
+    }
+}
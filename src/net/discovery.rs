@@ -0,0 +1,207 @@
+//! LAN discovery: hosts broadcast a small [`Beacon`] every second on
+//! [`DISCOVERY_PORT`], and the Lobby screen lists whatever it hears in an egui
+//! server browser, so nobody has to type IP addresses.
+//!
+//! ```text
+//! cargo run --bin server -- --name "Office"   # or: cargo run -- --host --name "Office"
+//! cargo run                                   # Play, then pick it in "LAN servers"
+//! ```
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use bevy_renet::renet::RenetServer;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use crate::level;
+use crate::states::AppState;
+use super::protocol::{self, Beacon};
+use super::{NetMode, NetPlayer};
+
+/// Beacons go to (and browsers listen on) this port on the broadcast address.
+pub const DISCOVERY_PORT: u16 = 5099;
+/// Seconds between beacons.
+const BEACON_INTERVAL: f32 = 1.0;
+/// A server is dropped from the list after this many seconds without a beacon.
+const BEACON_TIMEOUT: f64 = 3.5;
+/// Beacons are a few dozen bytes; anything bigger isn't one.
+const MAX_BEACON: usize = 512;
+
+/* ---------------- Server side ---------------- */
+
+/// Broadcasts the beacon while hosting over UDP.
+#[derive(Resource)]
+pub struct BeaconBroadcaster {
+    socket: UdpSocket,
+    timer: Timer,
+    name: String,
+    port: u16,
+}
+
+/// `--name NAME`, or something based on the user name.
+fn server_name() -> String {
+    if let Some(name) = super::arg_value("--name") {
+        return name;
+    }
+    match std::env::var("USER").or_else(|_| std::env::var("USERNAME")) {
+        Ok(user) => format!("{user}'s game"),
+        Err(_) => "Isometric server".to_string(),
+    }
+}
+
+/// `OnEnter(AppState::InGame)`, after `server::start_server`: starts announcing
+/// a UDP host. Discovery is a nicety, so failing here only logs.
+pub fn start_beacon(mut commands: Commands, mode: Res<NetMode>, server: Option<Res<RenetServer>>) {
+    let NetMode::Host { port } = *mode else { return };
+    if server.is_none() { return; }
+
+    let socket = UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .and_then(|s| s.set_broadcast(true).map(|_| s));
+    match socket {
+        Ok(socket) => commands.insert_resource(BeaconBroadcaster {
+            socket,
+            timer: Timer::from_seconds(BEACON_INTERVAL, TimerMode::Repeating),
+            name: server_name(),
+            port,
+        }),
+        Err(err) => warn!("no LAN discovery: {err}"),
+    }
+}
+
+pub fn stop_beacon(mut commands: Commands) {
+    commands.remove_resource::<BeaconBroadcaster>();
+}
+
+pub fn broadcast_beacon(
+    time: Res<Time<Real>>,
+    mut beacon: ResMut<BeaconBroadcaster>,
+    server: Res<RenetServer>,
+    current: Option<Res<level::CurrentLevel>>,
+    players: Query<(), With<NetPlayer>>,
+) {
+    if !beacon.timer.tick(time.delta()).just_finished() { return; }
+
+    let map = current.map(|c| c.name()).unwrap_or_default();
+    let players = players.iter().count();
+    // The host's own player (if any) doesn't take a client slot.
+    let max_players = players.saturating_sub(server.connected_clients()) + super::MAX_CLIENTS;
+    let msg = Beacon {
+        version: protocol::PROTOCOL_VERSION,
+        name: beacon.name.clone(),
+        map,
+        players: players as u16,
+        max_players: max_players as u16,
+        port: beacon.port,
+    };
+    let to = SocketAddr::from((Ipv4Addr::BROADCAST, DISCOVERY_PORT));
+    if let Err(err) = beacon.socket.send_to(&protocol::encode(&msg), to) {
+        debug!("beacon not sent: {err}");
+    }
+}
+
+/* ---------------- Client side ---------------- */
+
+/// A server heard from recently.
+#[derive(Clone, Debug)]
+pub struct LanServer {
+    pub addr: SocketAddr,
+    pub beacon: Beacon,
+    /// `Time<Real>` seconds of the last beacon.
+    pub last_seen: f64,
+}
+
+/// Listens for beacons while in the Lobby screen.
+#[derive(Resource, Default)]
+pub struct LanBrowser {
+    socket: Option<UdpSocket>,
+    /// In the order they were first heard, so the list doesn't jump around.
+    pub servers: Vec<LanServer>,
+}
+
+impl LanBrowser {
+    /// Adds or refreshes the server a beacon from `from` describes.
+    pub fn heard(&mut self, from: SocketAddr, beacon: Beacon, now: f64) {
+        let addr = SocketAddr::new(from.ip(), beacon.port);
+        let server = LanServer { addr, beacon, last_seen: now };
+        match self.servers.iter_mut().find(|s| s.addr == addr) {
+            Some(known) => *known = server,
+            None => self.servers.push(server),
+        }
+    }
+
+    /// Forgets servers that stopped announcing.
+    pub fn age_out(&mut self, now: f64) {
+        self.servers.retain(|s| now - s.last_seen < BEACON_TIMEOUT);
+    }
+}
+
+/// `OnEnter(AppState::Lobby)`.
+pub fn start_lan_browser(mut browser: ResMut<LanBrowser>) {
+    let socket = UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT)))
+        .and_then(|s| s.set_nonblocking(true).map(|_| s));
+    match socket {
+        Ok(socket) => browser.socket = Some(socket),
+        // Most likely another game on this machine is already listening.
+        Err(err) => warn!("can't listen for LAN servers on port {DISCOVERY_PORT}: {err}"),
+    }
+}
+
+/// `OnExit(AppState::Lobby)`.
+pub fn stop_lan_browser(mut commands: Commands) {
+    commands.insert_resource(LanBrowser::default());
+}
+
+/// Anything on the port that isn't a beacon we can read (other programs, or
+/// builds whose beacon changed shape) is dropped quietly: servers with another
+/// `PROTOCOL_VERSION` still show, greyed out, as long as they can be read.
+pub fn receive_beacons(time: Res<Time<Real>>, mut browser: ResMut<LanBrowser>) {
+    let now = time.elapsed_secs_f64();
+    let mut buf = [0; MAX_BEACON];
+    while let Some(Ok((len, from))) = browser.socket.as_ref().map(|s| s.recv_from(&mut buf)) {
+        match protocol::try_decode::<Beacon>(&buf[..len]) {
+            Ok(beacon) => browser.heard(from, beacon, now),
+            Err(err) => debug!("ignoring a packet from {from} on the discovery port: {err}"),
+        }
+    }
+    browser.age_out(now);
+}
+
+/// The "LAN servers" window in the Lobby screen; Join connects straight away.
+pub fn server_browser_panel(
+    mut contexts: EguiContexts,
+    browser: Res<LanBrowser>,
+    mut mode: ResMut<NetMode>,
+    mut next: ResMut<NextState<AppState>>,
+) -> Result {
+    egui::Window::new("LAN servers").resizable(false).show(contexts.ctx_mut()?, |ui| {
+        if browser.socket.is_none() {
+            ui.label(format!("Not listening (port {DISCOVERY_PORT} is taken)."));
+            return;
+        }
+        if browser.servers.is_empty() {
+            ui.label("Looking for servers...");
+            return;
+        }
+        egui::Grid::new("lan_servers").striped(true).show(ui, |ui| {
+            ui.label("name");
+            ui.label("map");
+            ui.label("players");
+            ui.label("address");
+            ui.end_row();
+            for s in &browser.servers {
+                let b = &s.beacon;
+                ui.label(&b.name);
+                ui.label(&b.map);
+                ui.label(format!("{}/{}", b.players, b.max_players));
+                ui.label(s.addr.to_string());
+                let compatible = b.version == protocol::PROTOCOL_VERSION;
+                let join = ui.add_enabled(compatible, egui::Button::new("Join"));
+                if !compatible {
+                    join.on_disabled_hover_text("different game version");
+                } else if join.clicked() {
+                    *mode = NetMode::Client { server: s.addr };
+                    next.set(AppState::Loading);
+                }
+                ui.end_row();
+            }
+        });
+    });
+    Ok(())
+}
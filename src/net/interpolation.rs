@@ -0,0 +1,147 @@
+//! Remote players are drawn a little in the past, between two buffered server
+//! snapshots, so they move smoothly even though snapshots arrive unevenly.
+//! Their `GridPos` stays the newest raw snapshot; only the `Transform` is smoothed.
+use bevy::prelude::*;
+use std::collections::VecDeque;
+use crate::constants;
+use crate::world;
+
+/// Oldest samples beyond this are dropped (half a second at 60 Hz).
+const MAX_SAMPLES: usize = 32;
+
+/// Tunables for remote player interpolation.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct Interpolation {
+    /// How far behind the newest snapshot remote players are drawn, in seconds.
+    /// Bigger hides more jitter and loss at the cost of showing older positions.
+    pub delay: f32,
+    /// When snapshots are late, keep moving players along their last velocity
+    /// for at most this long (seconds) before freezing them.
+    pub max_extrapolation: f32,
+    /// Draw raw (red) vs interpolated (green) positions. Toggle with F3.
+    pub debug: bool,
+}
+
+impl Default for Interpolation {
+    fn default() -> Self {
+        Self { delay: 0.1, max_extrapolation: 0.25, debug: false }
+    }
+}
+
+/// Estimated server tick clock, from snapshot arrival times.
+#[derive(Resource, Debug, Default)]
+pub struct SnapshotClock {
+    /// Server time minus local time, seconds.
+    offset: Option<f64>,
+}
+
+impl SnapshotClock {
+    /// Feeds in a snapshot for `tick` that arrived at local time `now`.
+    pub fn observe(&mut self, tick: u32, now: f64) {
+        let sample = tick as f64 / constants::TICK_HZ - now;
+        self.offset = Some(match self.offset {
+            // Ease towards new samples so one late packet doesn't jerk everyone,
+            // but jump if we are way off (first packets, server restart, long hitch).
+            Some(offset) if (sample - offset).abs() < 0.5 => offset + (sample - offset) * 0.05,
+            _ => sample,
+        });
+    }
+
+    /// The server tick to draw remote players at, `delay` seconds in the past.
+    pub fn render_tick(&self, now: f64, delay: f32) -> Option<f64> {
+        self.offset.map(|offset| (now + offset - delay as f64) * constants::TICK_HZ)
+    }
+}
+
+/// Recent server positions of one remote player, oldest first.
+#[derive(Component, Debug, Default)]
+pub struct SnapshotBuffer {
+    pub samples: VecDeque<(u32, Vec2)>,
+}
+
+impl SnapshotBuffer {
+    /// Inserts a sample in tick order (snapshots may arrive out of order).
+    pub fn push(&mut self, tick: u32, pos: Vec2) {
+        let i = self.samples.partition_point(|&(t, _)| t < tick);
+        if self.samples.get(i).is_some_and(|&(t, _)| t == tick) { return; }
+        self.samples.insert(i, (tick, pos));
+        while self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Position at (fractional) `tick`: interpolated between the samples around it,
+    /// extrapolated at most `max_extra` ticks past the newest one.
+    pub fn sample(&self, tick: f64, max_extra: f64) -> Option<Vec2> {
+        let &(first_t, first) = self.samples.front()?;
+        if tick <= first_t as f64 { return Some(first); }
+
+        let i = self.samples.partition_point(|&(t, _)| (t as f64) <= tick);
+        if let Some(&(t1, p1)) = self.samples.get(i) {
+            let (t0, p0) = self.samples[i - 1];
+            let s = (tick - t0 as f64) / (t1 - t0) as f64;
+            return Some(p0.lerp(p1, s as f32));
+        }
+
+        // Past the newest sample: carry on along the last velocity, briefly.
+        let (t1, p1) = self.samples[i - 1];
+        let Some(&(t0, p0)) = i.checked_sub(2).and_then(|j| self.samples.get(j)) else { return Some(p1) };
+        let ahead = (tick - t1 as f64).min(max_extra);
+        let vel = (p1 - p0) / (t1 - t0) as f32;
+        Some(p1 + vel * ahead as f32)
+    }
+}
+
+/// Draws remote players at their interpolated position (overrides
+/// `collision::sync_render_from_grid` for them).
+pub fn interpolate_remote_players(
+    time: Res<Time<Real>>,
+    settings: Res<Interpolation>,
+    clock: Res<SnapshotClock>,
+    mut players: Query<(&SnapshotBuffer, &mut Transform)>,
+) {
+    let Some(tick) = clock.render_tick(time.elapsed_secs_f64(), settings.delay) else { return };
+    let max_extra = settings.max_extrapolation as f64 * constants::TICK_HZ;
+
+    for (buffer, mut t) in &mut players {
+        if let Some(p) = buffer.sample(tick, max_extra) {
+            t.translation = world::grid_to_iso(p.x, p.y, constants::TILE_W, constants::TILE_H);
+        }
+    }
+}
+
+/// F3 toggles the interpolation debug overlay.
+pub fn toggle_interpolation_debug(keys: Res<ButtonInput<KeyCode>>, mut settings: ResMut<Interpolation>) {
+    if keys.just_pressed(KeyCode::F3) {
+        settings.debug = !settings.debug;
+    }
+}
+
+/// Red: newest raw snapshot and the buffered samples behind it. Green: what we draw.
+pub fn draw_interpolation_debug(
+    settings: Res<Interpolation>,
+    mut gizmos: Gizmos,
+    players: Query<(&world::GridPos, &SnapshotBuffer, &Transform)>,
+) {
+    if !settings.debug { return; }
+
+    let raw = Color::srgb(1.0, 0.2, 0.2);
+    let drawn = Color::srgb(0.2, 1.0, 0.3);
+    let lift = Vec3::Y * 0.6;
+    for (gp, buffer, t) in &players {
+        let newest = world::grid_to_iso(gp.x, gp.y, constants::TILE_W, constants::TILE_H);
+        gizmos.sphere(Isometry3d::from_translation(newest + lift), 0.2, raw);
+
+        let mut prev: Option<Vec3> = None;
+        for &(_, p) in &buffer.samples {
+            let here = world::grid_to_iso(p.x, p.y, constants::TILE_W, constants::TILE_H) + lift;
+            if let Some(prev) = prev {
+                gizmos.line(prev, here, raw.with_alpha(0.5));
+            }
+            prev = Some(here);
+        }
+
+        gizmos.sphere(Isometry3d::from_translation(t.translation + lift), 0.2, drawn);
+        gizmos.line(newest + lift, t.translation + lift, drawn);
+    }
+}
@@ -0,0 +1,126 @@
+//! Authoritative multiplayer over renet.
+//!
+//! The server owns every player's `world::GridPos`. Clients send their
+//! `collision::MoveIntent` each fixed tick, predict their own movement with it,
+//! and reconcile against the server's snapshots, which only cover nearby
+//! players and only what changed (see `snapshot`). Try it on one box over loopback:
+//!
+//! ```text
+//! cargo run -- --host                      # listen server on 127.0.0.1:5000
+//! cargo run -- --connect 127.0.0.1:5000    # as many clients as you like
+//! ```
+//!
+//! Or host and join through Steam lobbies instead (see `platform`). Every
+//! message on the wire is defined in `protocol`.
+use bevy::prelude::*;
+use bevy_renet::renet::{ConnectionConfig, DefaultChannel};
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::constants;
+use crate::world;
+use crate::states::AppState;
+
+pub mod client;
+pub mod discovery;
+pub mod interpolation;
+pub mod platform;
+pub mod protocol;
+pub mod server;
+pub mod sim;
+pub mod snapshot;
+pub mod stats;
+
+pub const DEFAULT_PORT: u16 = 5000;
+pub const MAX_CLIENTS: usize = 16;
+/// The host's own player id (netcode client ids are random, so 0 is free).
+pub const HOST_ID: u64 = 0;
+/// How many past inputs ride along with each new one, to survive packet loss.
+pub const INPUT_REDUNDANCY: usize = 3;
+
+/* ---------------- Mode / CLI ---------------- */
+
+/// Whether this process is offline, hosting, or a client of someone else.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NetMode {
+    #[default]
+    Offline,
+    /// Listen server: simulates everyone and also has a local player.
+    Host { port: u16 },
+    Client { server: SocketAddr },
+    /// Listen server for our `platform` lobby, over the platform's transport.
+    LobbyHost,
+    /// Client of the lobby `owner`, over the platform's transport.
+    LobbyClient { owner: platform::UserId },
+}
+
+/// The value after `flag` on the command line, if any.
+pub fn arg_value(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    args.position(|a| a == flag)?;
+    args.next()
+}
+
+/// `--port N`, or [`DEFAULT_PORT`].
+pub fn port_arg() -> u16 {
+    arg_value("--port").and_then(|p| p.parse().ok()).unwrap_or(DEFAULT_PORT)
+}
+
+impl NetMode {
+    /// `--host [--port N]` or `--connect ADDR`; anything else is offline.
+    pub fn from_args() -> Self {
+        if let Some(addr) = arg_value("--connect") {
+            match addr.parse() {
+                Ok(server) => return NetMode::Client { server },
+                Err(err) => error!("--connect {addr}: {err}; staying offline"),
+            }
+        }
+        if std::env::args().any(|a| a == "--host") {
+            return NetMode::Host { port: port_arg() };
+        }
+        NetMode::Offline
+    }
+
+    pub fn is_client(&self) -> bool {
+        matches!(self, NetMode::Client { .. } | NetMode::LobbyClient { .. })
+    }
+}
+
+/// Run condition: `collision::move_with_collision_system` drives the local player
+/// (offline or hosting). Clients move it in `client::predict_and_send` instead.
+pub fn simulates_locally(mode: Res<NetMode>) -> bool {
+    !mode.is_client()
+}
+
+/// Inputs and snapshots go unreliable (newer ones supersede old ones);
+/// the ordered channels are there for anything that must arrive.
+pub fn connection_config() -> ConnectionConfig {
+    ConnectionConfig {
+        server_channels_config: DefaultChannel::config(),
+        client_channels_config: DefaultChannel::config(),
+        ..default()
+    }
+}
+
+pub fn now() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+/* ---------------- Players ---------------- */
+
+/// A networked player; the local one has `world::LocalPlayer` as well.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NetPlayer {
+    pub id: u64,
+}
+
+/// Spawns somebody else's player (no visuals; clients add them in `setup::player_visuals`).
+pub fn spawn_remote_player(commands: &mut Commands, id: u64, gp: world::GridPos) -> Entity {
+    let p = world::grid_to_iso(gp.x, gp.y, constants::TILE_W, constants::TILE_H);
+    commands.spawn((
+        DespawnOnExit(AppState::InGame),
+        NetPlayer { id },
+        crate::collision::MoveIntent::default(),
+        gp,
+        Transform::from_translation(p),
+    )).id()
+}
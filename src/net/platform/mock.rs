@@ -0,0 +1,419 @@
+//! In-process [`Platform`]: every [`MockPlatform`] logged into the same
+//! [`MockNetwork`] sees the others' lobbies, invites and packets, the way Steam
+//! users would. Lets the lobby flows and the transport run in tests.
+use bevy::prelude::*;
+use bevy_renet::renet::{RenetClient, RenetServer};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use super::{LobbyEvent, LobbyId, Platform, UserId};
+
+/// The shared "platform backend". Clone it to hand to each user.
+#[derive(Clone, Default)]
+pub struct MockNetwork(Arc<Mutex<Hub>>);
+
+#[derive(Default)]
+struct Hub {
+    /// Users and lobbies share one id space.
+    next_id: u64,
+    names: HashMap<UserId, String>,
+    events: HashMap<UserId, Vec<LobbyEvent>>,
+    lobbies: HashMap<LobbyId, MockLobby>,
+    /// Users with a server transport up.
+    listening: HashSet<UserId>,
+    /// Open connections, (host, client).
+    links: HashSet<(UserId, UserId)>,
+    /// Packets waiting for each receiver, with their sender.
+    packets: HashMap<UserId, Vec<(UserId, Vec<u8>)>>,
+}
+
+struct MockLobby {
+    owner: UserId,
+    /// Oldest first; the next owner if the current one leaves.
+    members: Vec<UserId>,
+    max_members: usize,
+}
+
+impl Hub {
+    fn emit(&mut self, to: UserId, event: LobbyEvent) {
+        self.events.entry(to).or_default().push(event);
+    }
+
+    fn lobby_of(&self, user: UserId) -> Option<LobbyId> {
+        self.lobbies.iter().find(|(_, l)| l.members.contains(&user)).map(|(&id, _)| id)
+    }
+
+    fn leave(&mut self, user: UserId) {
+        let Some(id) = self.lobby_of(user) else { return };
+        let lobby = self.lobbies.get_mut(&id).expect("lobby_of found it");
+        lobby.members.retain(|&m| m != user);
+        let Some(&next_owner) = lobby.members.first() else {
+            self.lobbies.remove(&id);
+            return;
+        };
+        if lobby.owner == user {
+            lobby.owner = next_owner;
+        }
+        for m in lobby.members.clone() {
+            self.emit(m, LobbyEvent::MemberLeft { lobby: id, user });
+        }
+    }
+}
+
+impl MockNetwork {
+    /// A new user on this network.
+    pub fn login(&self, name: &str) -> MockPlatform {
+        let mut hub = self.hub();
+        hub.next_id += 1;
+        let user = hub.next_id;
+        hub.names.insert(user, name.to_string());
+        MockPlatform { network: self.clone(), user }
+    }
+
+    fn hub(&self) -> MutexGuard<'_, Hub> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// One user's view of a [`MockNetwork`].
+pub struct MockPlatform {
+    network: MockNetwork,
+    user: UserId,
+}
+
+impl Platform for MockPlatform {
+    fn name(&self) -> &'static str {
+        "Mock"
+    }
+
+    fn local_user(&self) -> UserId {
+        self.user
+    }
+
+    fn user_name(&self, user: UserId) -> String {
+        self.network.hub().names.get(&user).cloned().unwrap_or_else(|| user.to_string())
+    }
+
+    fn create_lobby(&mut self, max_members: usize) {
+        let mut hub = self.network.hub();
+        hub.leave(self.user);
+        hub.next_id += 1;
+        let lobby = hub.next_id;
+        hub.lobbies.insert(lobby, MockLobby { owner: self.user, members: vec![self.user], max_members });
+        hub.emit(self.user, LobbyEvent::Created { lobby });
+    }
+
+    fn join_lobby(&mut self, lobby: LobbyId) {
+        let mut hub = self.network.hub();
+        let Some(l) = hub.lobbies.get(&lobby) else {
+            hub.emit(self.user, LobbyEvent::Failed(format!("lobby {lobby} doesn't exist")));
+            return;
+        };
+        if l.members.len() >= l.max_members {
+            hub.emit(self.user, LobbyEvent::Failed(format!("lobby {lobby} is full")));
+            return;
+        }
+        hub.leave(self.user);
+        let l = hub.lobbies.get_mut(&lobby).expect("checked above");
+        let (owner, others) = (l.owner, l.members.clone());
+        l.members.push(self.user);
+        for m in others {
+            hub.emit(m, LobbyEvent::MemberJoined { lobby, user: self.user });
+        }
+        hub.emit(self.user, LobbyEvent::Entered { lobby, owner });
+    }
+
+    fn leave_lobby(&mut self) {
+        self.network.hub().leave(self.user);
+    }
+
+    fn invite(&mut self, user: UserId) {
+        let mut hub = self.network.hub();
+        if let Some(lobby) = hub.lobby_of(self.user) {
+            hub.emit(user, LobbyEvent::Invited { lobby, from: self.user });
+        }
+    }
+
+    fn lobby(&self) -> Option<LobbyId> {
+        self.network.hub().lobby_of(self.user)
+    }
+
+    fn owner(&self) -> Option<UserId> {
+        let hub = self.network.hub();
+        hub.lobby_of(self.user).map(|id| hub.lobbies[&id].owner)
+    }
+
+    fn members(&self) -> Vec<UserId> {
+        let hub = self.network.hub();
+        hub.lobby_of(self.user).map(|id| hub.lobbies[&id].members.clone()).unwrap_or_default()
+    }
+
+    fn poll(&mut self) -> Vec<LobbyEvent> {
+        self.network.hub().events.remove(&self.user).unwrap_or_default()
+    }
+
+    fn host(&mut self, world: &mut World) -> Result<(), String> {
+        self.network.hub().listening.insert(self.user);
+        world.insert_resource(MockServerTransport {
+            network: self.network.clone(),
+            user: self.user,
+            clients: HashSet::new(),
+        });
+        Ok(())
+    }
+
+    fn connect(&mut self, owner: UserId, world: &mut World) -> Result<(), String> {
+        let mut hub = self.network.hub();
+        if !hub.listening.contains(&owner) {
+            return Err(format!("{owner} isn't hosting"));
+        }
+        hub.links.insert((owner, self.user));
+        world.insert_resource(MockClientTransport { network: self.network.clone(), user: self.user, host: owner });
+        Ok(())
+    }
+
+    fn close(&mut self, world: &mut World) {
+        let mut hub = self.network.hub();
+        if world.remove_resource::<MockServerTransport>().is_some() {
+            hub.listening.remove(&self.user);
+            hub.links.retain(|&(host, _)| host != self.user);
+        }
+        if let Some(client) = world.remove_resource::<MockClientTransport>() {
+            hub.links.remove(&(client.host, self.user));
+        }
+        hub.packets.remove(&self.user);
+    }
+}
+
+/* ---------------- Transport ---------------- */
+
+/// Server end of the mock transport, inserted by [`MockPlatform::host`].
+#[derive(Resource)]
+pub struct MockServerTransport {
+    network: MockNetwork,
+    user: UserId,
+    /// Clients the `RenetServer` knows about.
+    clients: HashSet<UserId>,
+}
+
+impl MockServerTransport {
+    pub fn update(&mut self, server: &mut RenetServer) {
+        let mut hub = self.network.hub();
+        let linked: HashSet<UserId> =
+            hub.links.iter().filter(|&&(host, _)| host == self.user).map(|&(_, c)| c).collect();
+        for &c in linked.difference(&self.clients) {
+            server.add_connection(c);
+        }
+        for &c in self.clients.difference(&linked) {
+            server.remove_connection(c);
+        }
+        self.clients = linked;
+
+        for (from, packet) in hub.packets.remove(&self.user).unwrap_or_default() {
+            // Anything from a client that is already gone is dropped.
+            let _ = server.process_packet_from(&packet, from);
+        }
+    }
+
+    pub fn send_packets(&mut self, server: &mut RenetServer) {
+        let mut hub = self.network.hub();
+        for c in server.disconnections_id() {
+            hub.links.remove(&(self.user, c));
+            self.clients.remove(&c);
+            server.remove_connection(c);
+        }
+        for c in server.clients_id() {
+            let Ok(packets) = server.get_packets_to_send(c) else { continue };
+            let inbox = hub.packets.entry(c).or_default();
+            inbox.extend(packets.into_iter().map(|p| (self.user, p)));
+        }
+    }
+}
+
+/// Client end of the mock transport, inserted by [`MockPlatform::connect`].
+#[derive(Resource)]
+pub struct MockClientTransport {
+    network: MockNetwork,
+    user: UserId,
+    host: UserId,
+}
+
+impl MockClientTransport {
+    pub fn update(&mut self, client: &mut RenetClient) {
+        let mut hub = self.network.hub();
+        if !hub.links.contains(&(self.host, self.user)) {
+            client.disconnect_due_to_transport();
+            return;
+        }
+        client.set_connected();
+        for (from, packet) in hub.packets.remove(&self.user).unwrap_or_default() {
+            if from == self.host {
+                client.process_packet(&packet);
+            }
+        }
+    }
+
+    pub fn send_packets(&mut self, client: &mut RenetClient) {
+        let mut hub = self.network.hub();
+        if client.is_disconnected() {
+            hub.links.remove(&(self.host, self.user));
+            return;
+        }
+        let packets = client.get_packets_to_send();
+        let inbox = hub.packets.entry(self.host).or_default();
+        inbox.extend(packets.into_iter().map(|p| (self.user, p)));
+    }
+}
+
+pub fn server_update(mut transport: ResMut<MockServerTransport>, mut server: ResMut<RenetServer>) {
+    transport.update(&mut server);
+}
+
+pub fn server_send(mut transport: ResMut<MockServerTransport>, mut server: ResMut<RenetServer>) {
+    transport.send_packets(&mut server);
+}
+
+pub fn client_update(mut transport: ResMut<MockClientTransport>, mut client: ResMut<RenetClient>) {
+    transport.update(&mut client);
+}
+
+pub fn client_send(mut transport: ResMut<MockClientTransport>, mut client: ResMut<RenetClient>) {
+    transport.send_packets(&mut client);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_renet::renet::{DefaultChannel, ServerEvent};
+    use bevy_renet::{RenetClientPlugin, RenetReceive, RenetSend, RenetServerPlugin};
+    use crate::net::platform::{self, ActivePlatform};
+
+    #[test]
+    fn invite_join_and_leave() {
+        let net = MockNetwork::default();
+        let mut alice = net.login("alice");
+        let mut bob = net.login("bob");
+
+        alice.create_lobby(4);
+        let [LobbyEvent::Created { lobby }] = alice.poll()[..] else { panic!("no lobby") };
+        assert_eq!(alice.owner(), Some(alice.local_user()));
+
+        alice.invite(bob.local_user());
+        assert_eq!(bob.poll(), [LobbyEvent::Invited { lobby, from: alice.local_user() }]);
+
+        bob.join_lobby(lobby);
+        assert_eq!(bob.poll(), [LobbyEvent::Entered { lobby, owner: alice.local_user() }]);
+        assert_eq!(alice.poll(), [LobbyEvent::MemberJoined { lobby, user: bob.local_user() }]);
+        assert_eq!(bob.members(), [alice.local_user(), bob.local_user()]);
+        assert_eq!(bob.user_name(alice.local_user()), "alice");
+
+        bob.leave_lobby();
+        assert_eq!(alice.poll(), [LobbyEvent::MemberLeft { lobby, user: bob.local_user() }]);
+        assert_eq!(bob.lobby(), None);
+        assert_eq!(alice.members(), [alice.local_user()]);
+    }
+
+    #[test]
+    fn owner_leaving_hands_the_lobby_over() {
+        let net = MockNetwork::default();
+        let mut alice = net.login("alice");
+        let mut bob = net.login("bob");
+        let mut carol = net.login("carol");
+
+        alice.create_lobby(4);
+        let lobby = alice.lobby().unwrap();
+        bob.join_lobby(lobby);
+        carol.join_lobby(lobby);
+        alice.leave_lobby();
+
+        assert_eq!(carol.owner(), Some(bob.local_user()));
+        assert!(carol.poll().contains(&LobbyEvent::MemberLeft { lobby, user: alice.local_user() }));
+
+        bob.leave_lobby();
+        carol.leave_lobby();
+        bob.join_lobby(lobby);
+        assert!(matches!(bob.poll().last(), Some(LobbyEvent::Failed(_))), "empty lobbies go away");
+    }
+
+    #[test]
+    fn full_lobbies_refuse_joins() {
+        let net = MockNetwork::default();
+        let mut alice = net.login("alice");
+        let mut bob = net.login("bob");
+        alice.create_lobby(1);
+        bob.join_lobby(alice.lobby().unwrap());
+        assert!(matches!(bob.poll()[..], [LobbyEvent::Failed(_)]));
+        assert_eq!(bob.lobby(), None);
+    }
+
+    fn host_app(p: MockPlatform) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, RenetServerPlugin))
+            .insert_resource(ActivePlatform(Box::new(p)))
+            .insert_resource(RenetServer::new(crate::net::connection_config()))
+            .add_systems(PreUpdate, server_update.in_set(RenetReceive)
+                .after(RenetServerPlugin::update_system)
+                .before(RenetServerPlugin::emit_server_events_system)
+                .run_if(resource_exists::<MockServerTransport>))
+            .add_systems(PostUpdate, server_send.in_set(RenetSend).run_if(resource_exists::<MockServerTransport>));
+        platform::host_lobby(app.world_mut());
+        app
+    }
+
+    fn client_app(p: MockPlatform, owner: UserId) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, RenetClientPlugin))
+            .insert_resource(ActivePlatform(Box::new(p)))
+            .insert_resource(RenetClient::new(crate::net::connection_config()))
+            .add_systems(PreUpdate, client_update.in_set(RenetReceive)
+                .after(RenetClientPlugin::update_system)
+                .run_if(resource_exists::<MockClientTransport>))
+            .add_systems(PostUpdate, client_send.in_set(RenetSend).run_if(resource_exists::<MockClientTransport>));
+        platform::connect_lobby(owner)(app.world_mut());
+        app
+    }
+
+    fn server_events(app: &mut App) -> Vec<ServerEvent> {
+        app.world_mut().resource_mut::<Messages<ServerEvent>>().drain().collect()
+    }
+
+    #[test]
+    fn members_reach_the_owner_over_the_mock_transport() {
+        let net = MockNetwork::default();
+        let alice = net.login("alice");
+        let bob = net.login("bob");
+        let (alice_id, bob_id) = (alice.local_user(), bob.local_user());
+
+        let mut host = host_app(alice);
+        let mut client = client_app(bob, alice_id);
+        for _ in 0..3 {
+            host.update();
+            client.update();
+        }
+        assert_eq!(server_events(&mut host), [ServerEvent::ClientConnected { client_id: bob_id }]);
+        assert!(client.world().resource::<RenetClient>().is_connected());
+
+        client.world_mut().resource_mut::<RenetClient>().send_message(DefaultChannel::ReliableOrdered, b"hi".to_vec());
+        host.world_mut().resource_mut::<RenetServer>().send_message(bob_id, DefaultChannel::ReliableOrdered, b"yo".to_vec());
+        for _ in 0..3 {
+            client.update();
+            host.update();
+        }
+        let got = host.world_mut().resource_mut::<RenetServer>().receive_message(bob_id, DefaultChannel::ReliableOrdered);
+        assert_eq!(got.as_deref(), Some(&b"hi"[..]));
+        let got = client.world_mut().resource_mut::<RenetClient>().receive_message(DefaultChannel::ReliableOrdered);
+        assert_eq!(got.as_deref(), Some(&b"yo"[..]));
+
+        platform::close_transports(client.world_mut());
+        host.update();
+        assert!(matches!(server_events(&mut host)[..], [ServerEvent::ClientDisconnected { client_id, .. }] if client_id == bob_id));
+    }
+
+    #[test]
+    fn connecting_to_someone_not_hosting_fails() {
+        let net = MockNetwork::default();
+        let alice = net.login("alice");
+        let bob = net.login("bob");
+        let client = client_app(bob, alice.local_user());
+        assert!(!client.world().contains_resource::<RenetClient>());
+    }
+}
@@ -0,0 +1,213 @@
+//! Lobbies on a platform service: create one, invite friends, join theirs, and
+//! play over the platform's own networking instead of raw UDP. The game only
+//! talks to the [`Platform`] trait, implemented by Steam (`--features steam`,
+//! run with `--steam`) and by [`mock::MockNetwork`], an in-process stand-in
+//! the tests use.
+//!
+//! In the Lobby screen, H creates a lobby and starts hosting it; J accepts the
+//! last invite. Members joining the lobby connect to its owner.
+use bevy::prelude::*;
+use bevy_renet::renet::{RenetClient, RenetServer};
+use crate::states::AppState;
+use super::NetMode;
+
+pub mod mock;
+#[cfg(feature = "steam")]
+pub mod steam;
+
+/// A platform user (a Steam id, say). Also their renet client id.
+pub type UserId = u64;
+pub type LobbyId = u64;
+
+/// Something that happened to us or our lobby, as reported by [`Platform::poll`].
+#[derive(Message, Clone, Debug, PartialEq, Eq)]
+pub enum LobbyEvent {
+    /// Our `create_lobby` went through; we own `lobby` and are in it.
+    Created { lobby: LobbyId },
+    /// Our `join_lobby` went through; `owner` hosts the game.
+    Entered { lobby: LobbyId, owner: UserId },
+    MemberJoined { lobby: LobbyId, user: UserId },
+    MemberLeft { lobby: LobbyId, user: UserId },
+    /// `from` invited us to `lobby`.
+    Invited { lobby: LobbyId, from: UserId },
+    /// A create or join failed.
+    Failed(String),
+}
+
+/// Lobby and transport services of a platform.
+pub trait Platform: Send + Sync {
+    /// Shown in the Lobby screen.
+    fn name(&self) -> &'static str;
+    fn local_user(&self) -> UserId;
+    fn user_name(&self, user: UserId) -> String;
+
+    /// Answers later with `Created` or `Failed`.
+    fn create_lobby(&mut self, max_members: usize);
+    /// Answers later with `Entered` or `Failed`.
+    fn join_lobby(&mut self, lobby: LobbyId);
+    fn leave_lobby(&mut self);
+    fn invite(&mut self, user: UserId);
+
+    fn lobby(&self) -> Option<LobbyId>;
+    fn owner(&self) -> Option<UserId>;
+    fn members(&self) -> Vec<UserId>;
+
+    /// Runs the platform's callbacks and returns what happened since last time.
+    fn poll(&mut self) -> Vec<LobbyEvent>;
+
+    /// Inserts the server transport for the current lobby (a `RenetServer` is already there).
+    fn host(&mut self, world: &mut World) -> Result<(), String>;
+    /// Inserts the client transport to `owner` (a `RenetClient` is already there).
+    fn connect(&mut self, owner: UserId, world: &mut World) -> Result<(), String>;
+    /// Removes whatever `host` or `connect` inserted.
+    fn close(&mut self, world: &mut World);
+}
+
+/// The platform in use. Absent when playing over plain UDP.
+#[derive(Resource)]
+pub struct ActivePlatform(pub Box<dyn Platform>);
+
+impl ActivePlatform {
+    /// `--steam [--steam-app ID]` when built with the `steam` feature; otherwise none.
+    pub fn from_args() -> Option<Self> {
+        #[cfg(feature = "steam")]
+        if std::env::args().any(|a| a == "--steam") {
+            let app = super::arg_value("--steam-app").and_then(|a| a.parse().ok()).unwrap_or(steam::SPACEWAR);
+            match steam::SteamPlatform::init(app) {
+                Ok(p) => return Some(Self(Box::new(p))),
+                Err(err) => error!("can't start Steam: {err}; playing without it"),
+            }
+        }
+        None
+    }
+}
+
+/// What the Lobby screen shows, and the invite J accepts.
+#[derive(Resource, Debug, Default)]
+pub struct LobbyStatus {
+    pub pending_invite: Option<(LobbyId, UserId)>,
+    pub last_error: Option<String>,
+}
+
+/// Marks the Lobby screen's platform status text.
+#[derive(Component)]
+pub struct LobbyStatusText;
+
+/* ---------------- Transport commands ---------------- */
+
+fn with_platform(
+    world: &mut World,
+    f: impl FnOnce(&mut dyn Platform, &mut World) -> Result<(), String>,
+) -> Result<(), String> {
+    if !world.contains_resource::<ActivePlatform>() {
+        return Err("no platform".into());
+    }
+    world.resource_scope(|world, mut platform: Mut<ActivePlatform>| f(platform.0.as_mut(), world))
+}
+
+/// Command queued by `server::start_server` for [`NetMode::LobbyHost`].
+pub fn host_lobby(world: &mut World) {
+    if let Err(err) = with_platform(world, |p, w| p.host(w)) {
+        error!("can't host the lobby: {err}");
+        world.remove_resource::<RenetServer>();
+    }
+}
+
+/// Command queued by `client::start_client` for [`NetMode::LobbyClient`].
+pub fn connect_lobby(owner: UserId) -> impl FnOnce(&mut World) {
+    move |world: &mut World| {
+        if let Err(err) = with_platform(world, |p, w| p.connect(owner, w)) {
+            error!("can't connect to the lobby owner: {err}");
+            world.remove_resource::<RenetClient>();
+        }
+    }
+}
+
+/// Command queued when the server or client stops.
+pub fn close_transports(world: &mut World) {
+    let _ = with_platform(world, |p, w| {
+        p.close(w);
+        Ok(())
+    });
+}
+
+/* ---------------- Systems ---------------- */
+
+/// Every frame: turns platform callbacks into `LobbyEvent` messages.
+pub fn poll_platform(mut platform: ResMut<ActivePlatform>, mut events: MessageWriter<LobbyEvent>) {
+    events.write_batch(platform.0.poll());
+}
+
+/// Lobby screen: H hosts a new lobby, J accepts the last invite.
+pub fn lobby_platform_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut platform: ResMut<ActivePlatform>,
+    mut status: ResMut<LobbyStatus>,
+) {
+    if keys.just_pressed(KeyCode::KeyH) {
+        platform.0.create_lobby(super::MAX_CLIENTS + 1);
+    } else if keys.just_pressed(KeyCode::KeyJ) {
+        if let Some((lobby, _)) = status.pending_invite.take() {
+            platform.0.join_lobby(lobby);
+        }
+    }
+}
+
+/// Lobby screen: into the game once we are in a lobby, as its host if we
+/// created it, else as a client of the owner. Invites are remembered for J.
+pub fn follow_lobby_events(
+    mut events: MessageReader<LobbyEvent>,
+    mut mode: ResMut<NetMode>,
+    mut status: ResMut<LobbyStatus>,
+    mut next: ResMut<NextState<AppState>>,
+) {
+    for event in events.read() {
+        match *event {
+            LobbyEvent::Created { lobby } => {
+                info!("hosting lobby {lobby}");
+                *mode = NetMode::LobbyHost;
+                next.set(AppState::Loading);
+            }
+            LobbyEvent::Entered { lobby, owner } => {
+                info!("joined lobby {lobby}");
+                *mode = NetMode::LobbyClient { owner };
+                next.set(AppState::Loading);
+            }
+            LobbyEvent::Invited { lobby, from } => status.pending_invite = Some((lobby, from)),
+            LobbyEvent::Failed(ref err) => {
+                warn!("lobby: {err}");
+                status.last_error = Some(err.clone());
+            }
+            LobbyEvent::MemberJoined { .. } | LobbyEvent::MemberLeft { .. } => {}
+        }
+    }
+}
+
+/// `OnExit(AppState::InGame)`: leaves the lobby along with the game.
+pub fn leave_lobby(mut platform: ResMut<ActivePlatform>, mut mode: ResMut<NetMode>) {
+    if matches!(*mode, NetMode::LobbyHost | NetMode::LobbyClient { .. }) {
+        platform.0.leave_lobby();
+        *mode = NetMode::Offline;
+    }
+}
+
+/// Lobby screen: who we are on the platform, and any invite.
+pub fn update_lobby_status(
+    platform: Res<ActivePlatform>,
+    status: Res<LobbyStatus>,
+    mut text: Query<&mut Text, With<LobbyStatusText>>,
+) {
+    let Ok(mut text) = text.single_mut() else { return };
+    let p = &platform.0;
+    let mut lines = vec![format!("{} as {}. H: host a lobby", p.name(), p.user_name(p.local_user()))];
+    if let Some((_, from)) = status.pending_invite {
+        lines.push(format!("{} invited you. J: join", p.user_name(from)));
+    }
+    if let Some(err) = &status.last_error {
+        lines.push(err.clone());
+    }
+    let lines = lines.join("\n");
+    if text.0 != lines {
+        text.0 = lines;
+    }
+}
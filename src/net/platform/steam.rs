@@ -0,0 +1,178 @@
+//! [`Platform`] on Steam: lobbies through the matchmaking API, and renet over
+//! Steam networking sockets (peer to peer, relayed by Valve) via `renet_steam`.
+//! Needs the Steam client running and logged in.
+//!
+//! ```text
+//! cargo run --features steam -- --steam [--steam-app ID]
+//! ```
+use bevy::prelude::*;
+use bevy_renet::renet::RenetServer;
+use bevy_renet::steam::{AccessPermission, SteamClientTransport, SteamServerConfig, SteamServerTransport};
+use std::sync::{Arc, Mutex};
+use steamworks::{
+    CallbackHandle, ChatMemberStateChange, Client, GameLobbyJoinRequested, LobbyChatUpdate, LobbyType, SteamId,
+};
+use super::{LobbyEvent, LobbyId, Platform, UserId};
+
+/// Spacewar, Valve's public test app. Fine for development; ship with our own.
+pub const SPACEWAR: u32 = 480;
+/// Steam's limit on lobby size.
+const MAX_LOBBY_MEMBERS: usize = 250;
+
+type Events = Arc<Mutex<Vec<LobbyEvent>>>;
+
+fn push(events: &Events, event: LobbyEvent) {
+    events.lock().unwrap_or_else(|e| e.into_inner()).push(event);
+}
+
+pub struct SteamPlatform {
+    client: Client,
+    lobby: Option<steamworks::LobbyId>,
+    /// Filled by Steam callbacks during `run_callbacks`.
+    events: Events,
+    _callbacks: Vec<CallbackHandle>,
+}
+
+impl SteamPlatform {
+    pub fn init(app_id: u32) -> Result<Self, String> {
+        let client = Client::init_app(app_id).map_err(|e| e.to_string())?;
+        // Start talking to the relays now; the first connection is quicker.
+        client.networking_utils().init_relay_network_access();
+
+        let events = Events::default();
+        let ev = events.clone();
+        let chat = client.register_callback(move |update: LobbyChatUpdate| {
+            let (lobby, user) = (update.lobby.raw(), update.user_changed.raw());
+            push(&ev, match update.member_state_change {
+                ChatMemberStateChange::Entered => LobbyEvent::MemberJoined { lobby, user },
+                _ => LobbyEvent::MemberLeft { lobby, user },
+            });
+        });
+        let ev = events.clone();
+        // Also fires when the player accepts an invite in the Steam overlay.
+        let invites = client.register_callback(move |req: GameLobbyJoinRequested| {
+            push(&ev, LobbyEvent::Invited { lobby: req.lobby_steam_id.raw(), from: req.friend_steam_id.raw() });
+        });
+
+        Ok(Self { client, lobby: None, events, _callbacks: vec![chat, invites] })
+    }
+}
+
+impl Platform for SteamPlatform {
+    fn name(&self) -> &'static str {
+        "Steam"
+    }
+
+    fn local_user(&self) -> UserId {
+        self.client.user().steam_id().raw()
+    }
+
+    fn user_name(&self, user: UserId) -> String {
+        if user == self.local_user() {
+            return self.client.friends().name();
+        }
+        self.client.friends().get_friend(SteamId::from_raw(user)).name()
+    }
+
+    fn create_lobby(&mut self, max_members: usize) {
+        self.leave_lobby();
+        let ev = self.events.clone();
+        let max = max_members.min(MAX_LOBBY_MEMBERS) as u32;
+        self.client.matchmaking().create_lobby(LobbyType::FriendsOnly, max, move |result| {
+            push(&ev, match result {
+                Ok(lobby) => LobbyEvent::Created { lobby: lobby.raw() },
+                Err(err) => LobbyEvent::Failed(format!("can't create a lobby: {err}")),
+            });
+        });
+    }
+
+    fn join_lobby(&mut self, lobby: LobbyId) {
+        self.leave_lobby();
+        let ev = self.events.clone();
+        let client = self.client.clone();
+        self.client.matchmaking().join_lobby(steamworks::LobbyId::from_raw(lobby), move |result| {
+            push(&ev, match result {
+                Ok(lobby) => LobbyEvent::Entered {
+                    lobby: lobby.raw(),
+                    owner: client.matchmaking().lobby_owner(lobby).raw(),
+                },
+                Err(()) => LobbyEvent::Failed(format!("can't join lobby {}", lobby)),
+            });
+        });
+    }
+
+    fn leave_lobby(&mut self) {
+        if let Some(lobby) = self.lobby.take() {
+            self.client.matchmaking().leave_lobby(lobby);
+        }
+    }
+
+    fn invite(&mut self, user: UserId) {
+        let Some(lobby) = self.lobby else { return };
+        // Not wrapped by steamworks-rs yet, so this is what `Client::matchmaking()`
+        // does underneath, plus the call itself.
+        // SAFETY: `self.client` keeps the Steam API initialised for as long as we
+        // exist, which is all the v009 accessor (the one `Client::matchmaking()`
+        // uses) needs; we check it handed back an interface before calling
+        // through it. Both ids go by value and Steam keeps no pointer of ours.
+        unsafe {
+            let mm = steamworks::sys::SteamAPI_SteamMatchmaking_v009();
+            if mm.is_null() { return; }
+            steamworks::sys::SteamAPI_ISteamMatchmaking_InviteUserToLobby(mm, lobby.raw(), user);
+        }
+    }
+
+    fn lobby(&self) -> Option<LobbyId> {
+        self.lobby.map(|l| l.raw())
+    }
+
+    fn owner(&self) -> Option<UserId> {
+        self.lobby.map(|l| self.client.matchmaking().lobby_owner(l).raw())
+    }
+
+    fn members(&self) -> Vec<UserId> {
+        let Some(lobby) = self.lobby else { return Vec::new() };
+        self.client.matchmaking().lobby_members(lobby).iter().map(|m| m.raw()).collect()
+    }
+
+    fn poll(&mut self) -> Vec<LobbyEvent> {
+        self.client.run_callbacks();
+        let events = std::mem::take(&mut *self.events.lock().unwrap_or_else(|e| e.into_inner()));
+        for event in &events {
+            if let LobbyEvent::Created { lobby } | LobbyEvent::Entered { lobby, .. } = *event {
+                self.lobby = Some(steamworks::LobbyId::from_raw(lobby));
+            }
+        }
+        events
+    }
+
+    fn host(&mut self, world: &mut World) -> Result<(), String> {
+        let access_permission = match self.lobby {
+            Some(lobby) => AccessPermission::InLobby(lobby),
+            None => AccessPermission::FriendsOnly,
+        };
+        let config = SteamServerConfig { max_clients: crate::net::MAX_CLIENTS, access_permission };
+        let transport = SteamServerTransport::new(self.client.clone(), config).map_err(|e| format!("{e:?}"))?;
+        // bevy_renet's `SteamServerPlugin` looks for it as a non-send resource.
+        world.insert_non_send_resource(transport);
+        Ok(())
+    }
+
+    fn connect(&mut self, owner: UserId, world: &mut World) -> Result<(), String> {
+        let transport = SteamClientTransport::new(self.client.clone(), &SteamId::from_raw(owner))
+            .map_err(|e| format!("{e:?}"))?;
+        world.insert_resource(transport);
+        Ok(())
+    }
+
+    fn close(&mut self, world: &mut World) {
+        if let Some(mut transport) = world.remove_non_send_resource::<SteamServerTransport>() {
+            if let Some(mut server) = world.get_resource_mut::<RenetServer>() {
+                transport.disconnect_all(&mut server, true);
+            }
+        }
+        if let Some(mut transport) = world.remove_resource::<SteamClientTransport>() {
+            transport.disconnect();
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Everything that goes over the wire, in one place: client and server
+//! messages (and the `roster::Avatar` they carry), the LAN beacon, and how they are encoded (serde + bincode 2,
+//! standard config: little endian, variable-length integers).
+//!
+//! Bump [`PROTOCOL_VERSION`] whenever any of these types or their encoding
+//! change. Clients put their version in the netcode connect token's user data;
+//! a server with a different one answers [`ServerMessage::Rejected`] and drops
+//! them, and the LAN browser greys such servers out.
+use bevy::prelude::*;
+use bevy_renet::netcode::NETCODE_USER_DATA_BYTES;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fmt;
+use crate::roster::Avatar;
+
+/// Netcode's id for this game. Stays the same across versions so that
+/// mismatched clients still reach the server and hear why they can't play.
+pub const PROTOCOL_ID: u64 = 0x150_BE71;
+/// Version of the messages below.
+pub const PROTOCOL_VERSION: u32 = 6;
+/// Marks user data written by [`handshake_user_data`] ("ISOB").
+const HANDSHAKE_MAGIC: [u8; 4] = *b"ISOB";
+
+/* ---------------- Handshake ---------------- */
+
+/// What a client sends as netcode user data: magic, then our version.
+pub fn handshake_user_data() -> [u8; NETCODE_USER_DATA_BYTES] {
+    let mut data = [0; NETCODE_USER_DATA_BYTES];
+    data[..4].copy_from_slice(&HANDSHAKE_MAGIC);
+    data[4..8].copy_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+    data
+}
+
+/// Why the server turned a client away.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum RejectReason {
+    /// `client` is `None` for builds from before versions were exchanged.
+    VersionMismatch { server: u32, client: Option<u32> },
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RejectReason::VersionMismatch { server, client: Some(client) } => write!(
+                f,
+                "version mismatch: the server speaks protocol v{server}, this game v{client}",
+            ),
+            RejectReason::VersionMismatch { server, client: None } => {
+                write!(f, "version mismatch: the server speaks protocol v{server}, this game is older")
+            }
+        }
+    }
+}
+
+/// Server side of the handshake: is a client with this user data one of ours?
+pub fn check_handshake(user_data: &[u8; NETCODE_USER_DATA_BYTES]) -> Result<(), RejectReason> {
+    let client = (user_data[..4] == HANDSHAKE_MAGIC)
+        .then(|| u32::from_le_bytes(user_data[4..8].try_into().expect("four bytes")));
+    if client == Some(PROTOCOL_VERSION) {
+        Ok(())
+    } else {
+        Err(RejectReason::VersionMismatch { server: PROTOCOL_VERSION, client })
+    }
+}
+
+/* ---------------- Messages ---------------- */
+
+/// One fixed tick of player input.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct InputCmd {
+    pub seq: u32,
+    pub vel: [f32; 2],
+    pub step: [i32; 2],
+}
+
+impl InputCmd {
+    pub fn intent(&self) -> crate::collision::MoveIntent {
+        crate::collision::MoveIntent {
+            vel: Vec2::from_array(self.vel),
+            step: IVec2::from_array(self.step),
+        }
+    }
+}
+
+/// Client to server.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ClientMessage {
+    Inputs {
+        /// Newest snapshot tick we have applied: the server's next delta baseline.
+        snapshot_ack: u32,
+        /// Newest input last; older ones are resends (see `net::INPUT_REDUNDANCY`).
+        cmds: Vec<InputCmd>,
+    },
+    /// Reliable, once connected: who we play as.
+    Avatar(Avatar),
+    /// Reliable, once connected, if we are on a team (see `world::Team`).
+    JoinTeam(u8),
+    /// Reliable: tiles we explored for the first time, for our teammates.
+    Explored(Vec<[i16; 2]>),
+    /// Reliable: a tile we pinged on the minimap (see `ping`), for our teammates.
+    Ping([i16; 2]),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct PlayerState {
+    pub id: u64,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Server to client.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ServerMessage {
+    /// Unreliable, every tick. The players near the receiver (see
+    /// `net::snapshot`), as changes since the `baseline` snapshot.
+    Snapshot {
+        tick: u32,
+        /// The snapshot this one is a delta against; 0 for none (a full snapshot).
+        baseline: u32,
+        /// Last input `seq` the server has applied for the receiving client.
+        ack: u32,
+        /// Players that moved or came into view since `baseline`.
+        players: Vec<PlayerState>,
+        /// Players in `baseline` that left (the game or the receiver's view).
+        removed: Vec<u64>,
+    },
+    /// Reliable, just before the server disconnects the client.
+    Rejected(RejectReason),
+    /// Reliable: who player `id` is, and their team if they have one (see
+    /// `world::Team`). Sent to everyone when a player picks or joins a team,
+    /// and to newcomers for everyone already there.
+    Avatar { id: u64, avatar: Avatar, team: Option<u8> },
+    /// Reliable: tiles a teammate explored.
+    Explored(Vec<[i16; 2]>),
+    /// Reliable: a tile a teammate pinged.
+    Ping([i16; 2]),
+}
+
+/// Broadcast on the LAN by hosts (see `net::discovery`).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Beacon {
+    /// Only servers with our [`PROTOCOL_VERSION`] can be joined.
+    pub version: u32,
+    pub name: String,
+    pub map: String,
+    pub players: u16,
+    pub max_players: u16,
+    /// The game port; the address is wherever the beacon came from.
+    pub port: u16,
+}
+
+/* ---------------- Encoding ---------------- */
+
+pub fn encode<T: Serialize>(msg: &T) -> Vec<u8> {
+    bincode::serde::encode_to_vec(msg, bincode::config::standard())
+        .expect("net messages are plain data")
+}
+
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Option<T> {
+    try_decode(bytes).map_err(|err| warn!("dropping malformed message: {err}")).ok()
+}
+
+/// [`decode`] that leaves reporting to the caller, for traffic that may not
+/// be ours in the first place (see `net::discovery`).
+pub fn try_decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, bincode::error::DecodeError> {
+    bincode::serde::decode_from_slice(bytes, bincode::config::standard()).map(|(msg, _)| msg)
+}
+
+/* ---------------- Tests ---------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(seq: u32) -> InputCmd {
+        InputCmd { seq, vel: [3.5, -5.0], step: [1, 0] }
+    }
+
+    fn snapshot(players: usize, removed: usize) -> ServerMessage {
+        ServerMessage::Snapshot {
+            tick: 123_456,
+            baseline: 123_450,
+            ack: 7_890,
+            players: (0..players as u64)
+                .map(|i| PlayerState { id: 1_700_000_000_000_000_000 + i, x: 12.25, y: -3.5 })
+                .collect(),
+            removed: (0..removed as u64).map(|i| 1_700_000_000_000_000_100 + i).collect(),
+        }
+    }
+
+    fn inputs(n: u32) -> ClientMessage {
+        ClientMessage::Inputs { snapshot_ack: 123_450, cmds: (1..=n).map(input).collect() }
+    }
+
+    fn avatar() -> Avatar {
+        Avatar { name: "Juan".into(), variant: 2 }
+    }
+
+    /// A few freshly explored tiles, as after a step into the open.
+    fn explored(n: i16) -> Vec<[i16; 2]> {
+        (0..n).map(|i| [20 + i % 13, 30 + i / 13]).collect()
+    }
+
+    fn beacon() -> Beacon {
+        Beacon {
+            version: PROTOCOL_VERSION,
+            name: "Office".into(),
+            map: "start".into(),
+            players: 3,
+            max_players: 17,
+            port: 5000,
+        }
+    }
+
+    fn round_trip<T: Serialize + DeserializeOwned + PartialEq + fmt::Debug>(msg: T) -> usize {
+        let bytes = encode(&msg);
+        assert_eq!(decode::<T>(&bytes).as_ref(), Some(&msg));
+        bytes.len()
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        round_trip(input(1));
+        round_trip(inputs(3));
+        round_trip(inputs(0));
+        round_trip(PlayerState { id: u64::MAX, x: f32::MIN, y: f32::MAX });
+        round_trip(snapshot(0, 0));
+        round_trip(snapshot(16, 2));
+        round_trip(ServerMessage::Rejected(RejectReason::VersionMismatch { server: 2, client: Some(1) }));
+        round_trip(ServerMessage::Rejected(RejectReason::VersionMismatch { server: 2, client: None }));
+        round_trip(ClientMessage::Avatar(avatar()));
+        round_trip(ServerMessage::Avatar { id: 1_700_000_000_000_000_000, avatar: avatar(), team: None });
+        round_trip(ServerMessage::Avatar { id: 7, avatar: avatar(), team: Some(1) });
+        round_trip(ClientMessage::JoinTeam(2));
+        round_trip(ClientMessage::Explored(explored(40)));
+        round_trip(ServerMessage::Explored(explored(40)));
+        round_trip(ClientMessage::Ping([-3, 250]));
+        round_trip(ServerMessage::Ping([-3, 250]));
+        round_trip(beacon());
+    }
+
+    /// Sizes of typical messages. If one of these changes, the wire format
+    /// did: bump `PROTOCOL_VERSION` and update the numbers.
+    #[test]
+    fn message_sizes() {
+        assert_eq!(round_trip(input(1)), 11);
+        // Sent every tick: the newest input plus `INPUT_REDUNDANCY` resends.
+        assert_eq!(round_trip(inputs(crate::net::INPUT_REDUNDANCY as u32)), 40);
+        assert_eq!(round_trip(PlayerState { id: 1_700_000_000_000_000_000, x: 1.0, y: 2.0 }), 17);
+        // Sent every tick to every client; grows with the players nearby that moved.
+        assert_eq!(round_trip(snapshot(0, 0)), 16);
+        assert_eq!(round_trip(snapshot(1, 0)), 33);
+        assert_eq!(round_trip(snapshot(1, 1)), 42);
+        assert_eq!(round_trip(snapshot(crate::net::MAX_CLIENTS + 1, 0)), 305);
+        let rejected = ServerMessage::Rejected(RejectReason::VersionMismatch { server: 2, client: Some(1) });
+        assert_eq!(round_trip(rejected), 5);
+        assert_eq!(round_trip(ClientMessage::Avatar(avatar())), 7);
+        assert_eq!(round_trip(ServerMessage::Avatar { id: 1_700_000_000_000_000_000, avatar: avatar(), team: None }), 17);
+        assert_eq!(round_trip(ServerMessage::Avatar { id: 1_700_000_000_000_000_000, avatar: avatar(), team: Some(1) }), 18);
+        // Sent whenever a teammate steps into unexplored ground.
+        assert_eq!(round_trip(ClientMessage::Explored(explored(13))), 28);
+        assert_eq!(round_trip(ServerMessage::Explored(explored(13))), 28);
+        assert_eq!(round_trip(ClientMessage::Ping([40, 25])), 3);
+        assert_eq!(round_trip(ServerMessage::Ping([40, 25])), 3);
+        assert_eq!(round_trip(beacon()), 19);
+    }
+
+    #[test]
+    fn garbage_does_not_decode() {
+        assert_eq!(decode::<ServerMessage>(&[9, 9, 9]), None);
+        assert_eq!(decode::<ClientMessage>(&[]), None);
+        assert!(try_decode::<Beacon>(b"M-SEARCH * HTTP/1.1").is_err());
+    }
+
+    #[test]
+    fn handshake_checks_the_version() {
+        assert_eq!(check_handshake(&handshake_user_data()), Ok(()));
+
+        let mut newer = handshake_user_data();
+        newer[4..8].copy_from_slice(&(PROTOCOL_VERSION + 1).to_le_bytes());
+        let err = check_handshake(&newer).unwrap_err();
+        assert_eq!(err, RejectReason::VersionMismatch { server: PROTOCOL_VERSION, client: Some(PROTOCOL_VERSION + 1) });
+        assert!(err.to_string().contains("version mismatch"));
+
+        // Builds from before the handshake sent no user data at all.
+        let err = check_handshake(&[0; NETCODE_USER_DATA_BYTES]).unwrap_err();
+        assert_eq!(err, RejectReason::VersionMismatch { server: PROTOCOL_VERSION, client: None });
+    }
+}
@@ -0,0 +1,482 @@
+use bevy::prelude::*;
+use bevy_renet::netcode::{NetcodeServerTransport, ServerAuthentication, ServerConfig};
+use bevy_renet::renet::{DefaultChannel, RenetServer, ServerEvent};
+use std::collections::{HashMap, VecDeque};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use crate::collision;
+use crate::fog::{self, FogOfWar};
+use crate::level;
+use crate::ping::Pings;
+use crate::roster::Avatar;
+use crate::world;
+use super::platform;
+use super::sim::{NetSim, SimRelay};
+use super::snapshot::{self, SnapshotHistory, WorldState};
+use super::stats::NetStats;
+use super::protocol::{self, ClientMessage, InputCmd, PlayerState, ServerMessage};
+use super::{NetMode, NetPlayer};
+
+/// Inputs waiting for a fixed tick, per remote player. More than this and
+/// the client is running ahead of us; drop the oldest.
+const MAX_QUEUED_INPUTS: usize = 8;
+
+/// Server-side bookkeeping for one connected client.
+#[derive(Component, Debug, Default)]
+pub struct ClientInputs {
+    pub queue: VecDeque<InputCmd>,
+    /// Highest `seq` applied so far (echoed back as the snapshot `ack`).
+    pub last_applied: u32,
+}
+
+/// What one client has been told, for delta snapshots.
+#[derive(Component, Debug, Default)]
+pub struct ClientView {
+    /// Newest snapshot tick the client says it has applied.
+    pub acked: u32,
+    /// What each recent snapshot to this client described.
+    pub sent: SnapshotHistory,
+}
+
+/// Seconds a rejected client is given to read `ServerMessage::Rejected`
+/// before we hang up on it.
+const REJECT_GRACE: f64 = 1.0;
+
+/// Clients turned away by `check_versions`, and when to disconnect them.
+#[derive(Resource, Debug, Default)]
+pub struct Rejections(pub Vec<(u64, f64)>);
+
+/// Fixed ticks simulated since the server started.
+#[derive(Resource, Debug, Default)]
+pub struct ServerTick(pub u32);
+
+/// `OnEnter(AppState::InGame)` when hosting: opens the UDP socket (or the
+/// platform's transport) and makes the local player the host's `NetPlayer`.
+pub fn start_server(
+    mut commands: Commands,
+    mode: Res<NetMode>,
+    sim: Res<NetSim>,
+    local: Query<Entity, With<world::LocalPlayer>>,
+) {
+    match *mode {
+        NetMode::Host { port } => {
+            if !listen_udp(&mut commands, port, &sim) { return; }
+            info!("hosting on port {port}");
+        }
+        NetMode::LobbyHost => {}
+        _ => return,
+    }
+    commands.insert_resource(RenetServer::new(super::connection_config()));
+    if *mode == NetMode::LobbyHost {
+        // After the `RenetServer`, which it takes away again if it fails.
+        commands.queue(platform::host_lobby);
+    }
+    commands.insert_resource(ServerTick::default());
+    commands.insert_resource(Rejections::default());
+    for e in &local {
+        commands.entity(e).insert(NetPlayer { id: super::HOST_ID });
+    }
+}
+
+/// Netcode on `port`, behind a `SimRelay` if the simulator is on. False if
+/// the socket can't be opened.
+fn listen_udp(commands: &mut Commands, port: u16, sim: &NetSim) -> bool {
+    let public = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port));
+    // With the simulator on, netcode listens on a private port behind the relay.
+    let addr = if sim.enabled { SocketAddr::from((Ipv4Addr::LOCALHOST, 0)) } else { public };
+    let socket = match UdpSocket::bind(addr) {
+        Ok(socket) => socket,
+        Err(err) => {
+            error!("can't host on {addr}: {err}");
+            return false;
+        }
+    };
+    let config = ServerConfig {
+        current_time: super::now(),
+        max_clients: super::MAX_CLIENTS,
+        protocol_id: protocol::PROTOCOL_ID,
+        public_addresses: vec![SocketAddr::from((Ipv4Addr::LOCALHOST, port))],
+        authentication: ServerAuthentication::Unsecure,
+    };
+    let local_addr = socket.local_addr();
+    let transport = match NetcodeServerTransport::new(config, socket) {
+        Ok(transport) => transport,
+        Err(err) => {
+            error!("can't host on {addr}: {err}");
+            return false;
+        }
+    };
+
+    if sim.enabled {
+        let relay = local_addr.and_then(|server| SimRelay::bind(public, server));
+        match relay {
+            Ok(relay) => commands.insert_resource(relay),
+            Err(err) => {
+                error!("can't start the network simulator on {public}: {err}");
+                return false;
+            }
+        }
+        info!("network simulator on: {:?}", sim.conditions);
+    }
+    commands.insert_resource(transport);
+    true
+}
+
+/// `OnExit(AppState::InGame)`: kicks everyone and closes the socket.
+pub fn stop_server(mut commands: Commands, server: Option<ResMut<RenetServer>>) {
+    if let Some(mut server) = server {
+        server.disconnect_all();
+    }
+    commands.queue(platform::close_transports);
+    commands.remove_resource::<RenetServer>();
+    commands.remove_resource::<NetcodeServerTransport>();
+    commands.remove_resource::<ServerTick>();
+    commands.remove_resource::<Rejections>();
+    commands.remove_resource::<SimRelay>();
+}
+
+/// Turns away clients whose connect token carries a different protocol version
+/// (see `protocol::check_handshake`), telling them why first.
+pub fn check_versions(
+    time: Res<Time<Real>>,
+    mut events: MessageReader<ServerEvent>,
+    transport: Option<Res<NetcodeServerTransport>>,
+    mut server: ResMut<RenetServer>,
+    mut rejections: ResMut<Rejections>,
+    mut stats: ResMut<NetStats>,
+) {
+    let now = time.elapsed_secs_f64();
+    for event in events.read() {
+        let ServerEvent::ClientConnected { client_id } = *event else { continue };
+        // Platform transports carry no user data, so lobby games aren't checked.
+        let Some(data) = transport.as_ref().and_then(|t| t.user_data(client_id)) else { continue };
+        if let Err(reason) = protocol::check_handshake(&data) {
+            warn!("rejecting client {client_id}: {reason}");
+            let bytes = protocol::encode(&ServerMessage::Rejected(reason));
+            stats.sent(DefaultChannel::ReliableOrdered, bytes.len());
+            server.send_message(client_id, DefaultChannel::ReliableOrdered, bytes);
+            rejections.0.push((client_id, now + REJECT_GRACE));
+        }
+    }
+    rejections.0.retain(|&(client_id, at)| {
+        if at > now { return true; }
+        server.disconnect(client_id);
+        false
+    });
+}
+
+/// Spawns a player at the level's `"player"` spawn for each new client,
+/// and removes it again when they leave.
+pub fn handle_server_events(
+    mut commands: Commands,
+    mut events: MessageReader<ServerEvent>,
+    rejections: Res<Rejections>,
+    current: Option<Res<level::CurrentLevel>>,
+    levels: Res<Assets<level::Level>>,
+    players: Query<(Entity, &NetPlayer)>,
+) {
+    for event in events.read() {
+        match *event {
+            ServerEvent::ClientConnected { client_id } => {
+                if rejections.0.iter().any(|&(id, _)| id == client_id) { continue; }
+                let spawn = current
+                    .as_ref()
+                    .and_then(|c| levels.get(&c.0))
+                    .and_then(|l| l.spawn("player"))
+                    .map(|s| world::GridPos { x: s.x as f32, y: s.y as f32 })
+                    .unwrap_or(world::GridPos { x: 0.0, y: 0.0 });
+                let e = super::spawn_remote_player(&mut commands, client_id, spawn);
+                commands.entity(e).insert((ClientInputs::default(), ClientView::default()));
+                info!("client {client_id} joined");
+            }
+            ServerEvent::ClientDisconnected { client_id, ref reason } => {
+                for (e, p) in &players {
+                    if p.id == client_id {
+                        commands.entity(e).despawn();
+                    }
+                }
+                info!("client {client_id} left: {reason}");
+            }
+        }
+    }
+}
+
+/// What `receive_reliable` needs to know about a player.
+type TeamMember = (Entity, &'static NetPlayer, Option<&'static world::Team>, Has<ClientInputs>);
+
+/// Who hears what player `from` shares with `team`, out of `players` (id,
+/// team, is a client): the other clients on it, and whether the host's own
+/// player is on it too.
+fn team_recipients(
+    from: u64,
+    team: world::Team,
+    players: impl IntoIterator<Item = (u64, Option<world::Team>, bool)>,
+) -> (Vec<u64>, bool) {
+    let mut clients = Vec::new();
+    let mut host = false;
+    for (id, mate_team, is_client) in players {
+        if id == from || mate_team != Some(team) { continue; }
+        if is_client {
+            clients.push(id);
+        } else {
+            host = true;
+        }
+    }
+    (clients, host)
+}
+
+/// Sends `msg` to the clients on `team` other than `from`, and says whether
+/// the host's own player is on it too.
+fn relay_to_team(
+    server: &mut RenetServer,
+    stats: &mut NetStats,
+    players: &Query<TeamMember>,
+    from: u64,
+    team: &world::Team,
+    msg: &ServerMessage,
+) -> bool {
+    let everyone = players.iter().map(|(_, p, t, is_client)| (p.id, t.copied(), is_client));
+    let (clients, host) = team_recipients(from, *team, everyone);
+    let bytes = protocol::encode(msg);
+    for id in clients {
+        stats.sent(DefaultChannel::ReliableOrdered, bytes.len());
+        server.send_message(id, DefaultChannel::ReliableOrdered, bytes.clone());
+    }
+    host
+}
+
+/// Handles clients' reliable messages: the avatar they picked, their team,
+/// and tiles they explored or pinged, which go on to their teammates (the
+/// host too).
+pub fn receive_reliable(
+    mut commands: Commands,
+    mut server: ResMut<RenetServer>,
+    mut stats: ResMut<NetStats>,
+    mut fog: Option<ResMut<FogOfWar>>,
+    mut pings: Option<ResMut<Pings>>,
+    players: Query<TeamMember>,
+) {
+    for (e, p, team, is_client) in &players {
+        if !is_client { continue; }
+        while let Some(bytes) = server.receive_message(p.id, DefaultChannel::ReliableOrdered) {
+            stats.received(DefaultChannel::ReliableOrdered, bytes.len());
+            match protocol::decode(&bytes) {
+                Some(ClientMessage::Avatar(avatar)) => {
+                    let avatar = avatar.sanitized();
+                    info!("client {} plays {avatar}", p.id);
+                    commands.entity(e).insert(avatar);
+                }
+                Some(ClientMessage::JoinTeam(t)) => {
+                    commands.entity(e).insert(world::Team(t));
+                }
+                Some(ClientMessage::Explored(cells)) => {
+                    let Some(team) = team else { continue };
+                    let msg = ServerMessage::Explored(cells.clone());
+                    if relay_to_team(&mut server, &mut stats, &players, p.id, team, &msg) {
+                        if let Some(fog) = fog.as_mut() {
+                            fog.explore(fog::cells_from_wire(&cells));
+                        }
+                    }
+                }
+                Some(ClientMessage::Ping(cell)) => {
+                    let Some(team) = team else { continue };
+                    if relay_to_team(&mut server, &mut stats, &players, p.id, team, &ServerMessage::Ping(cell)) {
+                        if let Some(pings) = pings.as_mut() {
+                            pings.add((cell[0] as i32, cell[1] as i32), false);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Host: passes the tiles our own player explored on to its teammates.
+pub fn share_host_explored(
+    mut server: ResMut<RenetServer>,
+    mut stats: ResMut<NetStats>,
+    mut fog: ResMut<FogOfWar>,
+    local: Query<&world::Team, With<world::LocalPlayer>>,
+    clients: Query<(&NetPlayer, &world::Team), With<ClientInputs>>,
+) {
+    if fog.unshared.is_empty() { return; }
+    let cells = fog::cells_to_wire(fog.unshared.drain(..));
+    let Ok(team) = local.single() else { return };
+    let bytes = protocol::encode(&ServerMessage::Explored(cells));
+    for (p, _) in clients.iter().filter(|(_, t)| *t == team) {
+        stats.sent(DefaultChannel::ReliableOrdered, bytes.len());
+        server.send_message(p.id, DefaultChannel::ReliableOrdered, bytes.clone());
+    }
+}
+
+/// Host: passes the tiles we pinged on to our teammates.
+pub fn share_host_pings(
+    mut server: ResMut<RenetServer>,
+    mut stats: ResMut<NetStats>,
+    mut pings: ResMut<Pings>,
+    local: Query<&world::Team, With<world::LocalPlayer>>,
+    clients: Query<(&NetPlayer, &world::Team), With<ClientInputs>>,
+) {
+    if pings.unshared.is_empty() { return; }
+    let cells: Vec<(i32, i32)> = pings.unshared.drain(..).collect();
+    let Ok(team) = local.single() else { return };
+    for (x, y) in cells {
+        let bytes = protocol::encode(&ServerMessage::Ping([x as i16, y as i16]));
+        for (p, _) in clients.iter().filter(|(_, t)| *t == team) {
+            stats.sent(DefaultChannel::ReliableOrdered, bytes.len());
+            server.send_message(p.id, DefaultChannel::ReliableOrdered, bytes.clone());
+        }
+    }
+}
+
+/// Who a player is, as `broadcast_avatars` tells it.
+type AvatarSource = (&'static NetPlayer, &'static Avatar, Option<&'static world::Team>);
+
+/// Players whose avatar or team `broadcast_avatars` has to tell everyone about.
+type AvatarChanged = Or<(Changed<Avatar>, Changed<world::Team>)>;
+
+fn avatar_message((p, avatar, team): (&NetPlayer, &Avatar, Option<&world::Team>)) -> Vec<u8> {
+    protocol::encode(&ServerMessage::Avatar { id: p.id, avatar: avatar.clone(), team: team.map(|t| t.0) })
+}
+
+/// Tells everyone about avatars (and teams) that changed, and newcomers about
+/// everyone's. Only clients that got a player hear about them: not those
+/// `check_versions` is about to turn away.
+pub fn broadcast_avatars(
+    mut server: ResMut<RenetServer>,
+    mut stats: ResMut<NetStats>,
+    changed: Query<AvatarSource, AvatarChanged>,
+    avatars: Query<AvatarSource>,
+    clients: Query<&NetPlayer, With<ClientView>>,
+    newcomers: Query<&NetPlayer, Added<ClientView>>,
+) {
+    for player in &changed {
+        let bytes = avatar_message(player);
+        for client in &clients {
+            stats.sent(DefaultChannel::ReliableOrdered, bytes.len());
+            server.send_message(client.id, DefaultChannel::ReliableOrdered, bytes.clone());
+        }
+    }
+    for newcomer in &newcomers {
+        for player in &avatars {
+            let bytes = avatar_message(player);
+            stats.sent(DefaultChannel::ReliableOrdered, bytes.len());
+            server.send_message(newcomer.id, DefaultChannel::ReliableOrdered, bytes);
+        }
+    }
+}
+
+/// Drains client inputs into each player's queue, skipping resends we already
+/// have, and notes which snapshot each client has got to.
+pub fn receive_inputs(
+    mut server: ResMut<RenetServer>,
+    mut stats: ResMut<NetStats>,
+    mut players: Query<(&NetPlayer, &mut ClientInputs, &mut ClientView)>,
+) {
+    let mut by_id: HashMap<u64, (Mut<ClientInputs>, Mut<ClientView>)> =
+        players.iter_mut().map(|(p, inputs, view)| (p.id, (inputs, view))).collect();
+
+    for client_id in server.clients_id() {
+        while let Some(bytes) = server.receive_message(client_id, DefaultChannel::Unreliable) {
+            stats.received(DefaultChannel::Unreliable, bytes.len());
+            let Some(ClientMessage::Inputs { snapshot_ack, cmds }) = protocol::decode(&bytes) else { continue };
+            let Some((inputs, view)) = by_id.get_mut(&client_id) else { continue };
+            view.acked = view.acked.max(snapshot_ack);
+
+            for cmd in cmds {
+                let newest = inputs.queue.back().map_or(inputs.last_applied, |c| c.seq);
+                if cmd.seq > newest {
+                    inputs.queue.push_back(cmd);
+                }
+            }
+            while inputs.queue.len() > MAX_QUEUED_INPUTS {
+                inputs.queue.pop_front();
+            }
+        }
+    }
+}
+
+/// Fixed tick: applies one queued input per remote player, same as the client would.
+pub fn apply_client_inputs(
+    time: Res<Time>,
+    blocked: Res<world::Blocked>,
+    mut players: Query<(&mut world::GridPos, &mut ClientInputs)>,
+) {
+    for (mut gp, mut inputs) in &mut players {
+        let Some(cmd) = inputs.queue.pop_front() else { continue };
+        collision::simulate_move(&blocked, &mut gp, &cmd.intent(), time.delta_secs());
+        inputs.last_applied = cmd.seq;
+    }
+}
+
+/// Fixed tick: sends every client the players around it, as a delta against
+/// the last snapshot it acknowledged.
+pub fn broadcast_snapshots(
+    mut server: ResMut<RenetServer>,
+    mut tick: ResMut<ServerTick>,
+    mut stats: ResMut<NetStats>,
+    players: Query<(&NetPlayer, &world::GridPos)>,
+    mut clients: Query<(&NetPlayer, &world::GridPos, &ClientInputs, &mut ClientView)>,
+) {
+    tick.0 = tick.0.wrapping_add(1);
+    let everyone: Vec<PlayerState> = players
+        .iter()
+        .map(|(p, gp)| PlayerState { id: p.id, x: gp.x, y: gp.y })
+        .collect();
+    let nothing = WorldState::new();
+
+    for (p, gp, inputs, mut view) in &mut clients {
+        let state = snapshot::visible_to(&PlayerState { id: p.id, x: gp.x, y: gp.y }, &everyone);
+        // No usable baseline (just joined, or acks stalled): send everything.
+        let (baseline, base) = match view.sent.get(view.acked) {
+            Some(base) => (view.acked, base),
+            None => (0, &nothing),
+        };
+        let (changed, removed) = snapshot::diff(base, &state);
+        let msg = ServerMessage::Snapshot { tick: tick.0, baseline, ack: inputs.last_applied, players: changed, removed };
+        let bytes = protocol::encode(&msg);
+        stats.sent(DefaultChannel::Unreliable, bytes.len());
+        stats.snapshot(bytes.len());
+        server.send_message(p.id, DefaultChannel::Unreliable, bytes);
+
+        let acked = view.acked;
+        view.sent.forget_before(acked);
+        view.sent.push(tick.0, state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_teammates_hear_what_is_shared() {
+        let (red, blue) = (world::Team(0), world::Team(1));
+        // (id, team, is a client); the host's own player is not.
+        let players = [(1, Some(red), true), (2, Some(blue), true), (3, Some(red), true), (4, None, true), (9, Some(red), false)];
+        assert_eq!(team_recipients(1, red, players), (vec![3], true));
+        assert_eq!(team_recipients(2, blue, players), (vec![], false));
+        // The host sharing: teammates that are clients.
+        assert_eq!(team_recipients(9, red, players), (vec![1, 3], false));
+    }
+
+    #[test]
+    fn turned_away_clients_hear_no_avatars() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(RenetServer::new(super::super::connection_config()))
+            .init_resource::<NetStats>()
+            .add_systems(Update, broadcast_avatars);
+        let (joined, rejected) = (1, 2);
+        let mut server = app.world_mut().resource_mut::<RenetServer>();
+        server.add_connection(joined);
+        server.add_connection(rejected);
+        // `check_versions` turned `rejected` away, so it got no player.
+        app.world_mut().spawn((NetPlayer { id: joined }, ClientView::default(), Avatar { name: "Juan".into(), variant: 1 }));
+        app.update();
+
+        let mut server = app.world_mut().resource_mut::<RenetServer>();
+        assert!(!server.get_packets_to_send(joined).unwrap().is_empty());
+        assert!(server.get_packets_to_send(rejected).unwrap().is_empty());
+    }
+}
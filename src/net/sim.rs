@@ -0,0 +1,568 @@
+//! Network condition simulator: latency, jitter, loss, duplication and
+//! reordering on top of the real transport, for testing netcode on one box.
+//!
+//! When hosting with `--net-sim` (or any `--sim-*` flag) the public port is a
+//! [`SimRelay`] that forwards to the netcode socket through two
+//! [`LinkConditioner`]s, so every client sees the same bad network. F4 opens a
+//! panel to change the conditions while playing.
+//!
+//! ```text
+//! cargo run -- --host --sim-latency 80 --sim-jitter 20 --sim-loss 5 --sim-dup 1 --sim-reorder 2
+//! ```
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use std::collections::{hash_map::Entry, HashMap};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+
+/// Extra delay for packets picked to arrive out of order, seconds.
+const REORDER_HOLD: f64 = 0.05;
+/// Bigger than any netcode packet.
+const MAX_PACKET: usize = 1500;
+
+/// What the simulated network does to each packet (applied per direction).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinkConditions {
+    /// One-way delay, milliseconds.
+    pub latency_ms: f32,
+    /// Up to this much extra random delay, milliseconds.
+    pub jitter_ms: f32,
+    /// Chance (0..1) a packet is dropped.
+    pub loss: f32,
+    /// Chance (0..1) a packet is delivered twice.
+    pub duplicate: f32,
+    /// Chance (0..1) a packet is held back so later ones overtake it.
+    pub reorder: f32,
+}
+
+/// Network simulator settings.
+#[derive(Resource, Debug, Default)]
+pub struct NetSim {
+    /// Route hosted traffic through a [`SimRelay`]. Fixed once the server is up.
+    pub enabled: bool,
+    pub conditions: LinkConditions,
+    /// Show the egui panel (F4).
+    pub panel: bool,
+}
+
+impl NetSim {
+    /// `--net-sim`, `--sim-latency MS`, `--sim-jitter MS`, and `--sim-loss`,
+    /// `--sim-dup`, `--sim-reorder` as percentages.
+    pub fn from_args() -> Self {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let value = |flag: &str| {
+            args.iter()
+                .position(|a| a == flag)
+                .and_then(|i| args.get(i + 1))
+                .and_then(|v| v.parse::<f32>().ok())
+        };
+        let percent = |flag: &str| value(flag).map(|p| (p / 100.0).clamp(0.0, 1.0));
+
+        let c = LinkConditions {
+            latency_ms: value("--sim-latency").unwrap_or(0.0).max(0.0),
+            jitter_ms: value("--sim-jitter").unwrap_or(0.0).max(0.0),
+            loss: percent("--sim-loss").unwrap_or(0.0),
+            duplicate: percent("--sim-dup").unwrap_or(0.0),
+            reorder: percent("--sim-reorder").unwrap_or(0.0),
+        };
+        let enabled = args.iter().any(|a| a == "--net-sim" || a.starts_with("--sim-"));
+        Self { enabled, conditions: c, panel: false }
+    }
+}
+
+/* ---------------- Conditioner ---------------- */
+
+/// One direction of a simulated link: packets go in with `send` and come out
+/// of `receive` once their (randomised) delivery time has passed.
+pub struct LinkConditioner<T> {
+    rng: fastrand::Rng,
+    in_flight: Vec<(f64, T)>,
+}
+
+impl<T: Clone> LinkConditioner<T> {
+    pub fn new(seed: u64) -> Self {
+        Self { rng: fastrand::Rng::with_seed(seed), in_flight: Vec::new() }
+    }
+
+    /// Queues `packet` sent at time `now` (seconds), unless it is lost.
+    pub fn send(&mut self, now: f64, c: &LinkConditions, packet: T) {
+        if self.rng.f32() < c.loss { return; }
+
+        let copies = if self.rng.f32() < c.duplicate { 2 } else { 1 };
+        for _ in 0..copies {
+            let mut delay = (c.latency_ms + c.jitter_ms * self.rng.f32()) as f64 / 1000.0;
+            if self.rng.f32() < c.reorder {
+                delay += REORDER_HOLD;
+            }
+            self.in_flight.push((now + delay, packet.clone()));
+        }
+    }
+
+    /// Everything due by `now`, in arrival order.
+    pub fn receive(&mut self, now: f64) -> Vec<T> {
+        self.in_flight.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let due = self.in_flight.partition_point(|&(at, _)| at <= now);
+        self.in_flight.drain(..due).map(|(_, p)| p).collect()
+    }
+}
+
+/* ---------------- UDP relay ---------------- */
+
+/// Upstream sockets of clients we haven't heard from in this long (seconds)
+/// are closed; netcode has timed them out well before.
+const UPSTREAM_IDLE: f64 = 30.0;
+
+/// A client's own socket toward the netcode server.
+struct Upstream {
+    socket: UdpSocket,
+    /// When the client last sent us something.
+    last_heard: f64,
+}
+
+/// Sits on the host's public port and forwards to the netcode socket through
+/// a conditioner each way. Every client gets its own upstream socket, so the
+/// server still sees one address per client.
+#[derive(Resource)]
+pub struct SimRelay {
+    public: UdpSocket,
+    server: SocketAddr,
+    upstream: HashMap<SocketAddr, Upstream>,
+    to_server: LinkConditioner<(SocketAddr, Vec<u8>)>,
+    to_clients: LinkConditioner<(SocketAddr, Vec<u8>)>,
+}
+
+impl SimRelay {
+    pub fn bind(public: SocketAddr, server: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(public)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            public: socket,
+            server,
+            upstream: HashMap::new(),
+            to_server: LinkConditioner::new(fastrand::u64(..)),
+            to_clients: LinkConditioner::new(fastrand::u64(..)),
+        })
+    }
+
+    /// Moves whatever has arrived into the conditioners and sends whatever is
+    /// due. A failing socket call (say, a client's port closed, or the server
+    /// not up yet) is logged and costs only that packet.
+    pub fn pump(&mut self, now: f64, c: &LinkConditions) {
+        let mut buf = [0u8; MAX_PACKET];
+
+        while let Some((n, from)) = received(self.public.recv_from(&mut buf)) {
+            self.to_server.send(now, c, (from, buf[..n].to_vec()));
+            if let Some(upstream) = self.upstream.get_mut(&from) {
+                upstream.last_heard = now;
+            }
+        }
+        for (&client, upstream) in &self.upstream {
+            while let Some(n) = received(upstream.socket.recv(&mut buf)) {
+                self.to_clients.send(now, c, (client, buf[..n].to_vec()));
+            }
+        }
+
+        for (client, packet) in self.to_server.receive(now) {
+            let upstream = match self.upstream.entry(client) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => match connect_upstream(self.server) {
+                    Ok(socket) => e.insert(Upstream { socket, last_heard: now }),
+                    Err(err) => {
+                        warn!("net sim relay: no upstream socket for {client}: {err}");
+                        continue;
+                    }
+                },
+            };
+            if let Err(err) = upstream.socket.send(&packet) {
+                log_socket_error(&err);
+            }
+        }
+        for (client, packet) in self.to_clients.receive(now) {
+            if let Err(err) = self.public.send_to(&packet, client) {
+                log_socket_error(&err);
+            }
+        }
+
+        self.upstream.retain(|_, upstream| now - upstream.last_heard < UPSTREAM_IDLE);
+    }
+}
+
+fn connect_upstream(server: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+    socket.set_nonblocking(true)?;
+    socket.connect(server)?;
+    Ok(socket)
+}
+
+/// The packet `result` read, if any. Errors stop this round of reading (what
+/// is left waits for the next pump); a port that went away on the other end
+/// shows up here too, after the fact, as a refused connection.
+fn received<T>(result: io::Result<T>) -> Option<T> {
+    match result {
+        Ok(v) => Some(v),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => None,
+        Err(e) => {
+            log_socket_error(&e);
+            None
+        }
+    }
+}
+
+/// Refusals are routine (a client quit, the server is starting), so they
+/// don't get a warning each.
+fn log_socket_error(err: &io::Error) {
+    match err.kind() {
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset => debug!("net sim relay: {err}"),
+        _ => warn!("net sim relay: {err}"),
+    }
+}
+
+/// Runs just before renet reads its sockets and just after it writes them.
+pub fn pump_relay(time: Res<Time<Real>>, sim: Res<NetSim>, mut relay: ResMut<SimRelay>) {
+    relay.pump(time.elapsed_secs_f64(), &sim.conditions);
+}
+
+/* ---------------- Panel ---------------- */
+
+/// F4 toggles the network simulator panel.
+pub fn toggle_sim_panel(keys: Res<ButtonInput<KeyCode>>, mut sim: ResMut<NetSim>) {
+    if keys.just_pressed(KeyCode::F4) {
+        sim.panel = !sim.panel;
+    }
+}
+
+pub fn sim_panel(mut contexts: EguiContexts, mut sim: ResMut<NetSim>, relay: Option<Res<SimRelay>>) -> Result {
+    if !sim.panel { return Ok(()); }
+
+    let mut open = true;
+    egui::Window::new("Network simulator").open(&mut open).show(contexts.ctx_mut()?, |ui| {
+        if relay.is_none() {
+            ui.label("Not active: host with --net-sim to route traffic through it.");
+        }
+        let c = &mut sim.conditions;
+        ui.add(egui::Slider::new(&mut c.latency_ms, 0.0..=500.0).text("latency (ms)"));
+        ui.add(egui::Slider::new(&mut c.jitter_ms, 0.0..=200.0).text("jitter (ms)"));
+        ui.add(egui::Slider::new(&mut c.loss, 0.0..=0.5).text("loss"));
+        ui.add(egui::Slider::new(&mut c.duplicate, 0.0..=0.5).text("duplicate"));
+        ui.add(egui::Slider::new(&mut c.reorder, 0.0..=0.5).text("reorder"));
+        if ui.button("Perfect network").clicked() {
+            *c = LinkConditions::default();
+        }
+    });
+    sim.panel = open;
+    Ok(())
+}
+
+/* ---------------- Tests ---------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::time::TimeUpdateStrategy;
+    use bevy_renet::renet::{RenetClient, RenetServer};
+    use bevy_renet::{RenetClientPlugin, RenetServerPlugin};
+    use std::time::Duration;
+    use crate::collision::MoveIntent;
+    use crate::constants;
+    use crate::level;
+    use crate::net::{client, interpolation, server, NetPlayer};
+    use crate::fog::{FogOfWar, TileFog};
+    use crate::ping::Pings;
+    use crate::roster::Avatar;
+    use crate::states::AppState;
+    use crate::world;
+
+    const DT: f64 = 1.0 / constants::TICK_HZ;
+
+    fn headless_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(DT)))
+            .insert_resource(Time::<Fixed>::from_hz(constants::TICK_HZ))
+            .init_resource::<world::Blocked>()
+            .init_resource::<crate::net::stats::NetStats>();
+        app
+    }
+
+    fn server_app() -> App {
+        let mut app = headless_app();
+        app.add_plugins(RenetServerPlugin)
+            .init_resource::<Assets<level::Level>>()
+            .insert_resource(RenetServer::new(crate::net::connection_config()))
+            .init_resource::<server::ServerTick>()
+            .init_resource::<server::Rejections>()
+            .add_systems(Update, (
+                server::handle_server_events,
+                server::receive_reliable,
+                server::broadcast_avatars,
+            ).chain())
+            .add_systems(FixedUpdate, (
+                server::receive_inputs,
+                server::apply_client_inputs,
+                server::broadcast_snapshots,
+            ).chain());
+        app
+    }
+
+    fn client_app(id: u64) -> App {
+        let mut app = headless_app();
+        let mut renet = RenetClient::new(crate::net::connection_config());
+        renet.set_connected();
+        app.add_plugins((RenetClientPlugin, bevy::state::app::StatesPlugin))
+            .init_state::<AppState>()
+            .init_resource::<client::LastDisconnect>()
+            .insert_resource(FogOfWar::new(16, 16))
+            .init_resource::<Pings>()
+            .insert_resource(renet)
+            .insert_resource(client::ClientSession { next_seq: 1, ..default() })
+            .init_resource::<client::Reconciliation>()
+            .init_resource::<interpolation::SnapshotClock>()
+            .add_systems(FixedUpdate, client::predict_and_send)
+            .add_systems(Update, (
+                client::receive_reliable,
+                client::send_avatar,
+                client::apply_snapshots,
+                client::tag_avatars,
+                client::send_explored,
+                client::send_pings,
+            ).chain());
+        app.world_mut().spawn((
+            world::LocalPlayer,
+            NetPlayer { id },
+            MoveIntent::default(),
+            client::PredictionError::default(),
+            world::GridPos { x: 0.0, y: 0.0 },
+            Avatar { name: format!("Player{id}"), variant: 1 },
+            // Odd ids against even ones.
+            world::Team((id % 2) as u8),
+        ));
+        app
+    }
+
+    struct Peer {
+        id: u64,
+        app: App,
+        up: LinkConditioner<Vec<u8>>,
+        down: LinkConditioner<Vec<u8>>,
+    }
+
+    /// A server and `n` clients in one process, wired through conditioned links.
+    struct Harness {
+        server: App,
+        peers: Vec<Peer>,
+        conditions: LinkConditions,
+        now: f64,
+    }
+
+    impl Harness {
+        fn new(n: u64, conditions: LinkConditions) -> Self {
+            let mut server = server_app();
+            let peers = (1..=n)
+                .map(|id| {
+                    server.world_mut().resource_mut::<RenetServer>().add_connection(id);
+                    Peer { id, app: client_app(id), up: LinkConditioner::new(id), down: LinkConditioner::new(id + 100) }
+                })
+                .collect();
+            Self { server, peers, conditions, now: 0.0 }
+        }
+
+        fn step(&mut self) {
+            self.now += DT;
+            self.server.update();
+            for peer in &mut self.peers {
+                peer.app.update();
+
+                let sent = peer.app.world_mut().resource_mut::<RenetClient>().get_packets_to_send();
+                for packet in sent {
+                    peer.up.send(self.now, &self.conditions, packet);
+                }
+                let sent = self.server.world_mut().resource_mut::<RenetServer>().get_packets_to_send(peer.id).unwrap();
+                for packet in sent {
+                    peer.down.send(self.now, &self.conditions, packet);
+                }
+
+                for packet in peer.up.receive(self.now) {
+                    self.server.world_mut().resource_mut::<RenetServer>().process_packet_from(&packet, peer.id).unwrap();
+                }
+                for packet in peer.down.receive(self.now) {
+                    peer.app.world_mut().resource_mut::<RenetClient>().process_packet(&packet);
+                }
+            }
+        }
+
+        fn set_velocity(&mut self, peer: usize, vel: Vec2) {
+            let world = self.peers[peer].app.world_mut();
+            let mut q = world.query_filtered::<&mut MoveIntent, With<world::LocalPlayer>>();
+            q.single_mut(world).unwrap().vel = vel;
+        }
+
+        /// Where the server has each player.
+        fn server_positions(&mut self) -> HashMap<u64, Vec2> {
+            let world = self.server.world_mut();
+            let mut q = world.query::<(&NetPlayer, &world::GridPos)>();
+            q.iter(world).map(|(p, gp)| (p.id, Vec2::new(gp.x, gp.y))).collect()
+        }
+
+        /// Where one client has each player.
+        fn client_positions(&mut self, peer: usize) -> HashMap<u64, Vec2> {
+            let world = self.peers[peer].app.world_mut();
+            let mut q = world.query::<(&NetPlayer, &world::GridPos)>();
+            q.iter(world).map(|(p, gp)| (p.id, Vec2::new(gp.x, gp.y))).collect()
+        }
+    }
+
+    fn assert_converged(h: &mut Harness) {
+        let truth = h.server_positions();
+        assert_eq!(truth.len(), h.peers.len());
+        for peer in 0..h.peers.len() {
+            let seen = h.client_positions(peer);
+            assert_eq!(seen.len(), truth.len(), "client {peer} sees the wrong players");
+            for (id, pos) in &truth {
+                let d = seen[id].distance(*pos);
+                assert!(d < 1e-3, "client {peer} has player {id} at {}, server at {pos}", seen[id]);
+            }
+        }
+    }
+
+    fn walk_then_stop(h: &mut Harness) {
+        let dirs = [Vec2::X, Vec2::Y, Vec2::new(-1.0, -1.0).normalize()];
+        for (i, dir) in dirs.iter().enumerate().take(h.peers.len()) {
+            h.set_velocity(i, *dir * constants::MOVE_SPEED);
+        }
+        for _ in 0..90 { h.step(); }
+        for i in 0..h.peers.len() {
+            h.set_velocity(i, Vec2::ZERO);
+        }
+        // Long enough for everything in flight to land and be acked.
+        for _ in 0..120 { h.step(); }
+    }
+
+    #[test]
+    fn conditioner_is_transparent_when_perfect() {
+        let mut link = LinkConditioner::new(1);
+        for i in 0..10 {
+            link.send(0.0, &LinkConditions::default(), i);
+        }
+        assert_eq!(link.receive(0.0), (0..10).collect::<Vec<_>>());
+    }
+
+    /// Pumps `relay` (at time `now`) until `socket` has a packet, or gives up.
+    fn pump_until_received(relay: &mut SimRelay, now: f64, socket: &UdpSocket) -> Option<(Vec<u8>, SocketAddr)> {
+        let mut buf = [0u8; MAX_PACKET];
+        for _ in 0..100 {
+            relay.pump(now, &LinkConditions::default());
+            if let Ok((n, from)) = socket.recv_from(&mut buf) {
+                return Some((buf[..n].to_vec(), from));
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        None
+    }
+
+    #[test]
+    fn relay_survives_refused_packets_and_closes_idle_sockets() {
+        let localhost = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        // A port with nobody on it, until the server comes up below.
+        let server_addr = UdpSocket::bind(localhost).unwrap().local_addr().unwrap();
+        let mut relay = SimRelay::bind(localhost, server_addr).unwrap();
+        let public = relay.public.local_addr().unwrap();
+        let client = UdpSocket::bind(localhost).unwrap();
+        client.set_nonblocking(true).unwrap();
+
+        // Refused by the missing server, which the next calls hear about.
+        for i in 0..5 {
+            client.send_to(&[i], public).unwrap();
+            assert_eq!(pump_until_received(&mut relay, 0.0, &client), None);
+        }
+        assert_eq!(relay.upstream.len(), 1);
+
+        let server = UdpSocket::bind(server_addr).unwrap();
+        server.set_nonblocking(true).unwrap();
+        client.send_to(b"hello", public).unwrap();
+        let (packet, upstream) = pump_until_received(&mut relay, 1.0, &server).expect("nothing got through");
+        assert_eq!(packet, b"hello");
+        server.send_to(b"welcome", upstream).unwrap();
+        let (packet, _) = pump_until_received(&mut relay, 1.0, &client).expect("no reply");
+        assert_eq!(packet, b"welcome");
+
+        // A quiet client's socket goes, and comes back when it speaks again.
+        relay.pump(1.0 + UPSTREAM_IDLE, &LinkConditions::default());
+        assert!(relay.upstream.is_empty());
+        client.send_to(b"back", public).unwrap();
+        let (packet, _) = pump_until_received(&mut relay, 2.0 + UPSTREAM_IDLE, &server).expect("nothing got through");
+        assert_eq!(packet, b"back");
+        assert_eq!(relay.upstream.len(), 1);
+    }
+
+    #[test]
+    fn conditioner_delays_drops_and_duplicates() {
+        let c = LinkConditions { latency_ms: 100.0, loss: 0.2, duplicate: 0.2, ..default() };
+        let mut link = LinkConditioner::new(7);
+        for i in 0..1000 {
+            link.send(0.0, &c, i);
+        }
+        assert!(link.receive(0.099).is_empty());
+        let got = link.receive(0.1);
+        // ~800 survive, ~160 of those twice.
+        assert!((850..1050).contains(&got.len()), "{} delivered", got.len());
+        let mut unique = got.clone();
+        unique.dedup();
+        assert!(unique.len() < got.len());
+    }
+
+    #[test]
+    fn clients_converge_on_a_perfect_network() {
+        let mut h = Harness::new(3, LinkConditions::default());
+        walk_then_stop(&mut h);
+        assert_converged(&mut h);
+
+        assert!(h.server_positions()[&1].x > 5.0, "player 1 barely moved");
+
+        // Nothing lost, so the server agreed with every prediction: no corrections.
+        // (The test apps don't run `smooth_prediction_error`, so this is their sum.)
+        for peer in &mut h.peers {
+            let world = peer.app.world_mut();
+            let mut q = world.query::<&client::PredictionError>();
+            assert_eq!(q.single(world).unwrap().0, Vec2::ZERO);
+        }
+    }
+
+    #[test]
+    fn clients_converge_on_a_bad_network() {
+        let bad = LinkConditions { latency_ms: 60.0, jitter_ms: 30.0, loss: 0.1, duplicate: 0.05, reorder: 0.05 };
+        let mut h = Harness::new(3, bad);
+        walk_then_stop(&mut h);
+        assert_converged(&mut h);
+        assert!(h.server_positions().values().all(|p| p.length() > 1.0), "nobody moved");
+    }
+
+    /// Smoke test for the reliable messages: everyone learns everyone's
+    /// avatar and team, and explored tiles and pings reach teammates only.
+    #[test]
+    fn reliable_news_reaches_the_right_clients() {
+        let mut h = Harness::new(3, LinkConditions::default());
+        for _ in 0..10 { h.step(); }
+        let world = h.peers[0].app.world_mut();
+        world.resource_mut::<FogOfWar>().unshared.push((5, 5));
+        world.resource_mut::<Pings>().place((4, 7), true);
+        for _ in 0..20 { h.step(); }
+
+        for peer in &mut h.peers {
+            let world = peer.app.world_mut();
+            let mut q = world.query::<(&NetPlayer, &Avatar, &world::Team)>();
+            let mut seen: Vec<(u64, String, u8)> = q.iter(world).map(|(p, a, t)| (p.id, a.name.clone(), t.0)).collect();
+            seen.sort();
+            let everyone = [(1, "Player1".into(), 1), (2, "Player2".into(), 0), (3, "Player3".into(), 1)];
+            assert_eq!(seen, everyone, "client {}", peer.id);
+        }
+        // Odd ids against even ones: player 3 is player 1's teammate, player 2 is not.
+        let fog = |h: &Harness, peer: usize| h.peers[peer].app.world().resource::<FogOfWar>().tile((5, 5));
+        let pinged = |h: &Harness, peer: usize| {
+            h.peers[peer].app.world().resource::<Pings>().active.iter().map(|p| p.cell).collect::<Vec<_>>()
+        };
+        assert_eq!(fog(&h, 2), TileFog::Explored);
+        assert_eq!(pinged(&h, 2), [(4, 7)]);
+        assert_eq!(fog(&h, 1), TileFog::Unexplored);
+        assert_eq!(pinged(&h, 1), []);
+    }
+}
@@ -0,0 +1,161 @@
+//! Snapshot deltas and interest management. Each client only hears about the
+//! players within [`INTEREST_RADIUS`] chunks of its own, and only what changed
+//! since the last snapshot it acknowledged. Both ends keep a short
+//! [`SnapshotHistory`] of what each snapshot described, the server to build
+//! deltas against it and the client to apply them.
+use bevy::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use super::protocol::PlayerState;
+
+/// Interest chunks are this many tiles on a side.
+pub const CHUNK_SIZE: f32 = 8.0;
+/// Players further than this many chunks away (either axis) are not sent.
+pub const INTEREST_RADIUS: i32 = 2;
+/// Snapshots remembered per client (about a second at 60 Hz). A client whose
+/// acknowledgement is older than this gets a full snapshot instead of a delta.
+const HISTORY: usize = 64;
+
+/// The players one snapshot describes, by id.
+pub type WorldState = HashMap<u64, PlayerState>;
+
+/// The interest chunk grid position `(x, y)` is in.
+pub fn chunk_of(x: f32, y: f32) -> IVec2 {
+    IVec2::new((x / CHUNK_SIZE).floor() as i32, (y / CHUNK_SIZE).floor() as i32)
+}
+
+/// Whether a player at `(x, y)` is of interest to a viewer in chunk `viewer`.
+pub fn in_interest(viewer: IVec2, x: f32, y: f32) -> bool {
+    (chunk_of(x, y) - viewer).abs().max_element() <= INTEREST_RADIUS
+}
+
+/// What the player `viewer` hears about: the players of `everyone` within its
+/// interest area, and always itself.
+pub fn visible_to(viewer: &PlayerState, everyone: &[PlayerState]) -> WorldState {
+    let chunk = chunk_of(viewer.x, viewer.y);
+    everyone
+        .iter()
+        .filter(|s| s.id == viewer.id || in_interest(chunk, s.x, s.y))
+        .map(|s| (s.id, *s))
+        .collect()
+}
+
+/// `current` as changes against `baseline`: players that are new or have
+/// moved (by id), and ids that are gone.
+pub fn diff(baseline: &WorldState, current: &WorldState) -> (Vec<PlayerState>, Vec<u64>) {
+    let mut changed: Vec<PlayerState> =
+        current.values().filter(|s| baseline.get(&s.id) != Some(*s)).copied().collect();
+    let mut removed: Vec<u64> = baseline.keys().filter(|id| !current.contains_key(id)).copied().collect();
+    changed.sort_by_key(|s| s.id);
+    removed.sort_unstable();
+    (changed, removed)
+}
+
+/// Undoes [`diff`].
+pub fn apply(baseline: &WorldState, changed: &[PlayerState], removed: &[u64]) -> WorldState {
+    let mut state = baseline.clone();
+    for id in removed {
+        state.remove(id);
+    }
+    state.extend(changed.iter().map(|s| (s.id, *s)));
+    state
+}
+
+/// Recent snapshots by tick, oldest first.
+#[derive(Debug, Default)]
+pub struct SnapshotHistory(VecDeque<(u32, WorldState)>);
+
+impl SnapshotHistory {
+    pub fn push(&mut self, tick: u32, state: WorldState) {
+        self.0.push_back((tick, state));
+        while self.0.len() > HISTORY {
+            self.0.pop_front();
+        }
+    }
+
+    pub fn get(&self, tick: u32) -> Option<&WorldState> {
+        self.0.iter().find(|(t, _)| *t == tick).map(|(_, s)| s)
+    }
+
+    /// Drops snapshots older than `tick`, which can no longer be a baseline.
+    pub fn forget_before(&mut self, tick: u32) {
+        while self.0.front().is_some_and(|&(t, _)| t < tick) {
+            self.0.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(players: &[(u64, f32, f32)]) -> WorldState {
+        players.iter().map(|&(id, x, y)| (id, PlayerState { id, x, y })).collect()
+    }
+
+    #[test]
+    fn deltas_only_carry_changes() {
+        let before = state(&[(1, 0.0, 0.0), (2, 5.0, 5.0), (3, 9.0, 9.0)]);
+        let after = state(&[(1, 0.0, 0.0), (2, 5.5, 5.0), (4, 1.0, 1.0)]);
+
+        let (changed, removed) = diff(&before, &after);
+        let ids: Vec<u64> = changed.iter().map(|s| s.id).collect();
+        assert_eq!(ids, [2, 4], "player 1 stood still");
+        assert_eq!(removed, [3]);
+        assert_eq!(apply(&before, &changed, &removed), after);
+
+        // Against nothing, a delta is the whole state.
+        let (changed, removed) = diff(&WorldState::new(), &after);
+        assert_eq!(changed.len(), 3);
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn interest_is_a_square_of_chunks() {
+        let viewer = chunk_of(3.0, 3.0);
+        let reach = CHUNK_SIZE * (INTEREST_RADIUS + 1) as f32;
+        assert!(in_interest(viewer, reach - 0.1, -CHUNK_SIZE * INTEREST_RADIUS as f32));
+        assert!(!in_interest(viewer, reach, 0.0));
+        assert!(!in_interest(viewer, 0.0, -reach));
+    }
+
+    #[test]
+    fn players_leave_and_reenter_interest() {
+        let far = CHUNK_SIZE * (INTEREST_RADIUS + 2) as f32;
+        let viewer = PlayerState { id: 1, x: 0.0, y: 0.0 };
+        let near = [viewer, PlayerState { id: 2, x: 3.0, y: 1.0 }, PlayerState { id: 3, x: 2.0, y: 2.0 }];
+        let mut away = near;
+        away[2] = PlayerState { id: 3, x: far, y: far };
+
+        // What the client rebuilds from each delta matches what the server meant.
+        let seen = visible_to(&viewer, &near);
+        assert_eq!(seen.len(), 3);
+        let (changed, removed) = diff(&seen, &visible_to(&viewer, &away));
+        assert!(changed.is_empty());
+        assert_eq!(removed, [3], "player 3 left the viewer's interest");
+        let without = apply(&seen, &changed, &removed);
+        assert_eq!(without, visible_to(&viewer, &away));
+
+        let (changed, removed) = diff(&without, &visible_to(&viewer, &near));
+        assert_eq!(changed, [near[2]], "player 3 came back");
+        assert!(removed.is_empty());
+        assert_eq!(apply(&without, &changed, &removed), seen);
+
+        // However far away, a player always sees itself.
+        let alone = visible_to(&away[2], &away);
+        assert_eq!(alone.keys().copied().collect::<Vec<_>>(), [3]);
+    }
+
+    #[test]
+    fn history_keeps_recent_baselines() {
+        let mut history = SnapshotHistory::default();
+        for tick in 1..=100 {
+            history.push(tick, state(&[(1, tick as f32, 0.0)]));
+        }
+        assert!(history.get(100 - HISTORY as u32).is_none());
+        assert_eq!(history.get(100).unwrap()[&1].x, 100.0);
+
+        history.forget_before(90);
+        assert!(history.get(89).is_none());
+        assert!(history.get(90).is_some());
+    }
+}
@@ -0,0 +1,169 @@
+//! Network debug overlay (F2): renet's RTT / loss / bandwidth graphs per
+//! connection, plus our own per-channel byte counts, server tick time and
+//! snapshot sizes.
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use bevy_renet::renet::{DefaultChannel, RenetClient, RenetServer, ServerEvent};
+use renet_visualizer::{RenetClientVisualizer, RenetServerVisualizer};
+use std::time::Instant;
+
+/// How many frames of history the renet graphs keep.
+pub const GRAPH_SAMPLES: usize = 200;
+
+pub type ServerVisualizer = RenetServerVisualizer<GRAPH_SAMPLES>;
+pub type ClientVisualizer = RenetClientVisualizer<GRAPH_SAMPLES>;
+
+const CHANNELS: [(DefaultChannel, &str); 3] = [
+    (DefaultChannel::Unreliable, "unreliable"),
+    (DefaultChannel::ReliableUnordered, "reliable unordered"),
+    (DefaultChannel::ReliableOrdered, "reliable ordered"),
+];
+
+/// Byte counts for one channel, one direction.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Traffic {
+    pub sent: usize,
+    pub received: usize,
+}
+
+/// Numbers renet doesn't track for us. Updated by the net systems as they go.
+#[derive(Resource, Debug, Default)]
+pub struct NetStats {
+    /// Show the overlay (F2).
+    pub visible: bool,
+    /// Message bytes per second on each `DefaultChannel`, over the last full second.
+    pub per_channel: [Traffic; 3],
+    counting: [Traffic; 3],
+    window: f32,
+    /// Server fixed tick (inputs, simulation, snapshots), milliseconds.
+    pub tick_ms: f32,
+    pub tick_ms_max: f32,
+    tick_started: Option<Instant>,
+    /// Size of the last snapshot sent (server) or received (client), bytes.
+    pub snapshot_bytes: usize,
+    pub snapshot_bytes_max: usize,
+}
+
+impl NetStats {
+    pub fn sent(&mut self, channel: DefaultChannel, bytes: usize) {
+        self.counting[u8::from(channel) as usize].sent += bytes;
+    }
+
+    pub fn received(&mut self, channel: DefaultChannel, bytes: usize) {
+        self.counting[u8::from(channel) as usize].received += bytes;
+    }
+
+    pub fn snapshot(&mut self, bytes: usize) {
+        self.snapshot_bytes = bytes;
+        self.snapshot_bytes_max = self.snapshot_bytes_max.max(bytes);
+    }
+}
+
+/// First in the server's fixed tick.
+pub fn begin_server_tick(mut stats: ResMut<NetStats>) {
+    stats.tick_started = Some(Instant::now());
+}
+
+/// Last in the server's fixed tick.
+pub fn end_server_tick(mut stats: ResMut<NetStats>) {
+    let Some(started) = stats.tick_started.take() else { return };
+    let ms = started.elapsed().as_secs_f32() * 1000.0;
+    // Smoothed so the number is readable; the max catches spikes.
+    stats.tick_ms += (ms - stats.tick_ms) * 0.1;
+    stats.tick_ms_max = stats.tick_ms_max.max(ms);
+}
+
+/// Turns the byte counters into per-second rates once a second.
+pub fn roll_net_stats(time: Res<Time<Real>>, mut stats: ResMut<NetStats>) {
+    stats.window += time.delta_secs();
+    if stats.window < 1.0 { return; }
+
+    let window = stats.window;
+    stats.per_channel = stats.counting.map(|t| Traffic {
+        sent: (t.sent as f32 / window) as usize,
+        received: (t.received as f32 / window) as usize,
+    });
+    stats.counting = default();
+    stats.window = 0.0;
+}
+
+/// Feeds renet's own numbers into the graphs.
+pub fn update_visualizers(
+    mut events: MessageReader<ServerEvent>,
+    server: Option<Res<RenetServer>>,
+    client: Option<Res<RenetClient>>,
+    mut server_vis: ResMut<ServerVisualizer>,
+    mut client_vis: ResMut<ClientVisualizer>,
+) {
+    for event in events.read() {
+        match *event {
+            ServerEvent::ClientConnected { client_id } => server_vis.add_client(client_id),
+            ServerEvent::ClientDisconnected { client_id, .. } => server_vis.remove_client(client_id),
+        }
+    }
+    if let Some(server) = server {
+        server_vis.update(&server);
+    }
+    if let Some(client) = client {
+        client_vis.add_network_info(client.network_info());
+    }
+}
+
+/// `OnExit(AppState::InGame)`: forget the last session's graphs and peaks.
+pub fn reset_net_stats(mut commands: Commands, stats: Res<NetStats>) {
+    commands.insert_resource(NetStats { visible: stats.visible, ..default() });
+    commands.insert_resource(ServerVisualizer::default());
+    commands.insert_resource(ClientVisualizer::default());
+}
+
+/// F2 toggles the overlay.
+pub fn toggle_net_stats(keys: Res<ButtonInput<KeyCode>>, mut stats: ResMut<NetStats>) {
+    if keys.just_pressed(KeyCode::F2) {
+        stats.visible = !stats.visible;
+    }
+}
+
+pub fn net_stats_panel(
+    mut contexts: EguiContexts,
+    stats: Res<NetStats>,
+    server: Option<Res<RenetServer>>,
+    client: Option<Res<RenetClient>>,
+    mut server_vis: ResMut<ServerVisualizer>,
+    client_vis: Res<ClientVisualizer>,
+) -> Result {
+    if !stats.visible { return Ok(()); }
+    let ctx = contexts.ctx_mut()?;
+
+    if server.is_some() {
+        server_vis.show_window(ctx);
+    }
+    if client.is_some() {
+        client_vis.show_window(ctx);
+    }
+
+    egui::Window::new("Net stats").resizable(false).show(ctx, |ui| {
+        if server.is_none() && client.is_none() {
+            ui.label("Offline.");
+            return;
+        }
+        if server.is_some() {
+            ui.label(format!("tick: {:.2} ms (max {:.2} ms)", stats.tick_ms, stats.tick_ms_max));
+        }
+        ui.label(format!("snapshot: {} B (max {} B)", stats.snapshot_bytes, stats.snapshot_bytes_max));
+        ui.separator();
+        egui::Grid::new("net_channels").striped(true).show(ui, |ui| {
+            ui.label("channel");
+            ui.label("sent B/s");
+            ui.label("received B/s");
+            ui.end_row();
+            for (channel, name) in CHANNELS {
+                let t = stats.per_channel[u8::from(channel) as usize];
+                ui.label(name);
+                ui.label(t.sent.to_string());
+                ui.label(t.received.to_string());
+                ui.end_row();
+            }
+        });
+    });
+    Ok(())
+}
@@ -0,0 +1,505 @@
+use bevy::prelude::*;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use crate::collision;
+use crate::cursor;
+use crate::world;
+
+/* ---------------- Search settings ---------------- */
+
+/// Which neighbours a tile has.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Connectivity {
+    Four,
+    Eight,
+}
+
+/// When a diagonal step is allowed to pass a blocked orthogonal neighbour.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CornerRule {
+    /// Diagonals ignore their orthogonal neighbours.
+    Always,
+    /// At least one of the two orthogonal neighbours must be open.
+    OneOpen,
+    /// Both orthogonal neighbours must be open (no corner cutting).
+    BothOpen,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PathSettings {
+    pub connectivity: Connectivity,
+    pub corners: CornerRule,
+    /// The grid is unbounded, so give up after expanding this many tiles.
+    pub max_expansions: usize,
+}
+
+impl Default for PathSettings {
+    fn default() -> Self {
+        Self {
+            connectivity: Connectivity::Eight,
+            corners: CornerRule::BothOpen,
+            max_expansions: 4096,
+        }
+    }
+}
+
+/// Extra cost of entering a tile (mud, stairs...). Tiles not listed cost 1.0.
+#[derive(Resource, Default, Debug)]
+pub struct TileCosts(pub HashMap<(i32, i32), f32>);
+
+impl TileCosts {
+    pub fn cost(&self, cell: (i32, i32)) -> f32 {
+        self.0.get(&cell).copied().unwrap_or(1.0)
+    }
+
+    /// Cheapest possible tile, used to keep the heuristic admissible.
+    fn min_cost(&self) -> f32 {
+        self.0.values().copied().fold(1.0, f32::min)
+    }
+}
+
+/* ---------------- A* ---------------- */
+
+const ORTHOGONAL: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const DIAGONAL: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+/// Open-set entry; ordered so `BinaryHeap` pops the lowest `f` first.
+struct Node {
+    f: f32,
+    cell: (i32, i32),
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool { self.f == other.f }
+}
+impl Eq for Node {}
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering { other.f.total_cmp(&self.f) }
+}
+
+fn heuristic(a: (i32, i32), b: (i32, i32), connectivity: Connectivity) -> f32 {
+    let dx = (a.0 - b.0).abs() as f32;
+    let dy = (a.1 - b.1).abs() as f32;
+    match connectivity {
+        Connectivity::Four => dx + dy,
+        // Octile distance
+        Connectivity::Eight => dx.max(dy) + (std::f32::consts::SQRT_2 - 1.0) * dx.min(dy),
+    }
+}
+
+/// Finds the cheapest path from `start` to `goal` (both included) around
+/// `blocked` cells. Returns `None` if the goal is blocked or unreachable
+/// within `settings.max_expansions`.
+pub fn find_path(
+    blocked: &world::Blocked,
+    costs: &TileCosts,
+    start: (i32, i32),
+    goal: (i32, i32),
+    settings: &PathSettings,
+) -> Option<Vec<(i32, i32)>> {
+    if blocked.is_blocked(goal) { return None; }
+    if start == goal { return Some(vec![start]); }
+
+    let h_scale = costs.min_cost();
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+    let mut g: HashMap<(i32, i32), f32> = HashMap::new();
+
+    g.insert(start, 0.0);
+    open.push(Node { f: heuristic(start, goal, settings.connectivity) * h_scale, cell: start });
+
+    let mut expansions = 0;
+    while let Some(Node { f, cell }) = open.pop() {
+        if cell == goal {
+            let mut path = vec![goal];
+            let mut c = goal;
+            while let Some(&prev) = came_from.get(&c) {
+                path.push(prev);
+                c = prev;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        let g_here = g[&cell];
+        // Stale heap entry (a cheaper route to `cell` was found after it was pushed).
+        if f > g_here + heuristic(cell, goal, settings.connectivity) * h_scale + f32::EPSILON {
+            continue;
+        }
+
+        expansions += 1;
+        if expansions > settings.max_expansions { return None; }
+
+        let diagonals: &[(i32, i32)] = match settings.connectivity {
+            Connectivity::Four => &[],
+            Connectivity::Eight => &DIAGONAL,
+        };
+        for &(dx, dy) in ORTHOGONAL.iter().chain(diagonals) {
+            let next = (cell.0 + dx, cell.1 + dy);
+            if blocked.is_blocked(next) { continue; }
+
+            let step = if dx != 0 && dy != 0 {
+                let open_a = !blocked.is_blocked((cell.0 + dx, cell.1));
+                let open_b = !blocked.is_blocked((cell.0, cell.1 + dy));
+                let allowed = match settings.corners {
+                    CornerRule::Always => true,
+                    CornerRule::OneOpen => open_a || open_b,
+                    CornerRule::BothOpen => open_a && open_b,
+                };
+                if !allowed { continue; }
+                std::f32::consts::SQRT_2
+            } else {
+                1.0
+            };
+
+            let tentative = g_here + step * costs.cost(next);
+            if g.get(&next).is_none_or(|&old| tentative < old) {
+                g.insert(next, tentative);
+                came_from.insert(next, cell);
+                let f = tentative + heuristic(next, goal, settings.connectivity) * h_scale;
+                open.push(Node { f, cell: next });
+            }
+        }
+    }
+
+    None
+}
+
+/* ---------------- Click-to-move ---------------- */
+
+/// Tunables for click-to-move.
+#[derive(Resource, Debug)]
+pub struct ClickToMove {
+    pub speed: f32, // tiles per second (before tile cost)
+    pub search: PathSettings,
+}
+
+impl Default for ClickToMove {
+    fn default() -> Self {
+        Self { speed: 4.0, search: PathSettings::default() }
+    }
+}
+
+/// Remaining waypoints (cell centres) the entity is walking through.
+#[derive(Component, Debug, Default)]
+pub struct PathFollow {
+    pub waypoints: VecDeque<(i32, i32)>,
+    /// Where we were last tick, and for how long we have not moved from there.
+    last_pos: Option<Vec2>,
+    stuck_for: f32,
+    /// Whether we already planned again after getting stuck.
+    replanned: bool,
+}
+
+impl PathFollow {
+    pub fn new(waypoints: impl IntoIterator<Item = (i32, i32)>) -> Self {
+        Self { waypoints: waypoints.into_iter().collect(), ..default() }
+    }
+}
+
+/// How long a mover may push without moving before `follow_path` plans again.
+const STUCK_SECONDS: f32 = 0.25;
+
+/// P cycles the search mode: 4-way -> 8-way (no corner cutting) -> 8-way (one side open) -> 8-way (always).
+pub fn cycle_path_mode(keys: Res<ButtonInput<KeyCode>>, mut settings: ResMut<ClickToMove>) {
+    if !keys.just_pressed(KeyCode::KeyP) { return; }
+    let search = &mut settings.search;
+    (search.connectivity, search.corners) = match (search.connectivity, search.corners) {
+        (Connectivity::Four, _) => (Connectivity::Eight, CornerRule::BothOpen),
+        (Connectivity::Eight, CornerRule::BothOpen) => (Connectivity::Eight, CornerRule::OneOpen),
+        (Connectivity::Eight, CornerRule::OneOpen) => (Connectivity::Eight, CornerRule::Always),
+        (Connectivity::Eight, CornerRule::Always) => (Connectivity::Four, CornerRule::BothOpen),
+    };
+    info!("path mode: {:?} / {:?}", search.connectivity, search.corners);
+}
+
+/// Left click on a tile: plan a path from the player's cell and start walking it.
+pub fn click_to_move(
+    mut commands: Commands,
+    mut clicks: MessageReader<cursor::TileClicked>,
+    settings: Res<ClickToMove>,
+    blocked: Res<world::Blocked>,
+    costs: Res<TileCosts>,
+    player_q: Query<(Entity, &world::GridPos), With<world::LocalPlayer>>,
+) {
+    let Some(goal) = clicks.read().last().map(|c| c.cell) else { return };
+    let Ok((player, gp)) = player_q.single() else { return };
+    let start = world::cell_at(gp.x, gp.y);
+
+    match find_path(&blocked, &costs, start, goal, &settings.search) {
+        Some(path) => {
+            // Skip our own cell; we walk from where we stand to the next one.
+            commands.entity(player).insert(PathFollow::new(path.into_iter().skip(1)));
+        }
+        None => debug!("no path from {start:?} to {goal:?}"),
+    }
+}
+
+/// Right click on a tile logs what the pathfinder sees there.
+pub fn inspect_tile(
+    mut clicks: MessageReader<cursor::TileRightClicked>,
+    blocked: Res<world::Blocked>,
+    costs: Res<TileCosts>,
+) {
+    for ev in clicks.read() {
+        info!("tile {:?}: blocked = {}, cost = {}", ev.cell, blocked.is_blocked(ev.cell), costs.cost(ev.cell));
+    }
+}
+
+/// Fixed tick, before the move: steers the `MoveIntent` through `PathFollow`
+/// waypoints, slower on costly tiles. Steering every tick rather than every
+/// frame means no tick carries us past a waypoint, however many run per frame.
+///
+/// Diagonals past a wall (`CornerRule::Always` and `OneOpen`) may be too tight
+/// for the mover's footprint (`collision::try_move`). A mover that stops
+/// moving plans again from where it stands without cutting corners, and gives
+/// up if it gets stuck a second time.
+pub fn follow_path(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<ClickToMove>,
+    blocked: Res<world::Blocked>,
+    costs: Res<TileCosts>,
+    mut movers: Query<(Entity, &world::GridPos, &mut collision::MoveIntent, &mut PathFollow)>,
+) {
+    let dt = time.delta_secs();
+    if dt <= 0.0 { return; }
+    for (entity, gp, mut intent, mut path) in &mut movers {
+        let here = Vec2::new(gp.x, gp.y);
+        if path.last_pos.replace(here) == Some(here) {
+            path.stuck_for += dt;
+        } else {
+            path.stuck_for = 0.0;
+        }
+        if path.stuck_for >= STUCK_SECONDS {
+            let start = world::cell_at(here.x, here.y);
+            let search = PathSettings { corners: CornerRule::BothOpen, ..settings.search };
+            let replan = path.waypoints.back().filter(|_| !path.replanned)
+                .and_then(|&goal| find_path(&blocked, &costs, start, goal, &search));
+            let Some(replan) = replan else {
+                debug!("stuck at {here}, giving up on the path");
+                intent.vel = Vec2::ZERO;
+                commands.entity(entity).remove::<PathFollow>();
+                continue;
+            };
+            // Keeping our own cell first recentres us before setting off again.
+            *path = PathFollow { replanned: true, ..PathFollow::new(replan) };
+        }
+        // Drop waypoints we have reached.
+        while let Some(&next) = path.waypoints.front() {
+            if here.distance(Vec2::new(next.0 as f32, next.1 as f32)) > 1e-3 { break; }
+            path.waypoints.pop_front();
+        }
+        let Some(&next) = path.waypoints.front() else {
+            intent.vel = Vec2::ZERO;
+            commands.entity(entity).remove::<PathFollow>();
+            continue;
+        };
+
+        let to_target = Vec2::new(next.0 as f32, next.1 as f32) - here;
+        let speed = settings.speed / costs.cost(next);
+        // Slow down on the last tick before a waypoint so we land on it exactly.
+        intent.vel = to_target.normalize() * speed.min(to_target.length() / dt);
+    }
+}
+
+/// Arrow-key movement takes over from click-to-move, cancelling the path.
+/// Runs after `collision::follow_player`, which clears `vel` when no arrow is held.
+pub fn cancel_path_on_keys(
+    mut commands: Commands,
+    movers: Query<(Entity, &collision::MoveIntent), With<PathFollow>>,
+) {
+    for (entity, intent) in &movers {
+        if intent.vel != Vec2::ZERO {
+            commands.entity(entity).remove::<PathFollow>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::time::TimeUpdateStrategy;
+    use std::time::Duration;
+    use crate::constants;
+
+    fn walls(cells: &[(i32, i32)]) -> world::Blocked {
+        world::Blocked(cells.iter().copied().collect())
+    }
+
+    fn search(connectivity: Connectivity, corners: CornerRule) -> PathSettings {
+        PathSettings { connectivity, corners, ..default() }
+    }
+
+    /// Steps are to a neighbour, around walls, and cut no more corners than `corners` lets them.
+    fn assert_walkable(path: &[(i32, i32)], blocked: &world::Blocked, settings: &PathSettings) {
+        for pair in path.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            let (dx, dy) = (x1 - x0, y1 - y0);
+            assert!(dx.abs() <= 1 && dy.abs() <= 1 && (dx, dy) != (0, 0), "jump in {path:?}");
+            assert!(!blocked.is_blocked(pair[1]), "through a wall in {path:?}");
+            if dx != 0 && dy != 0 {
+                assert_eq!(settings.connectivity, Connectivity::Eight, "diagonal in {path:?}");
+                let open = [(x1, y0), (x0, y1)].iter().filter(|&&c| !blocked.is_blocked(c)).count();
+                let needed = match settings.corners {
+                    CornerRule::Always => 0,
+                    CornerRule::OneOpen => 1,
+                    CornerRule::BothOpen => 2,
+                };
+                assert!(open >= needed, "cut a corner in {path:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn four_way_paths_take_no_diagonals() {
+        let open = walls(&[]);
+        let costs = TileCosts::default();
+
+        let four = search(Connectivity::Four, CornerRule::BothOpen);
+        let path = find_path(&open, &costs, (0, 0), (3, 2), &four).unwrap();
+        assert_eq!(path.len(), 6);
+        assert_walkable(&path, &open, &four);
+
+        let eight = search(Connectivity::Eight, CornerRule::BothOpen);
+        let path = find_path(&open, &costs, (0, 0), (3, 2), &eight).unwrap();
+        assert_eq!(path.len(), 4);
+        assert_walkable(&path, &open, &eight);
+
+        assert_eq!(find_path(&open, &costs, (2, 2), (2, 2), &eight), Some(vec![(2, 2)]));
+    }
+
+    #[test]
+    fn corner_rules_decide_which_corners_are_cut() {
+        let costs = TileCosts::default();
+        let one_side = walls(&[(1, 0)]);
+        let both_sides = walls(&[(1, 0), (0, 1)]);
+        let cases = [
+            (CornerRule::Always, 2, 2),
+            (CornerRule::OneOpen, 2, 4),
+            (CornerRule::BothOpen, 3, 7),
+        ];
+        for (corners, past_one, past_both) in cases {
+            let settings = search(Connectivity::Eight, corners);
+            let path = find_path(&one_side, &costs, (0, 0), (1, 1), &settings).unwrap();
+            assert_eq!(path.len(), past_one, "{corners:?} past one wall: {path:?}");
+            assert_walkable(&path, &one_side, &settings);
+            let path = find_path(&both_sides, &costs, (0, 0), (1, 1), &settings).unwrap();
+            assert_eq!(path.len(), past_both, "{corners:?} between two walls: {path:?}");
+            assert_walkable(&path, &both_sides, &settings);
+        }
+    }
+
+    #[test]
+    fn costly_tiles_are_walked_around() {
+        let open = walls(&[]);
+        let settings = search(Connectivity::Four, CornerRule::BothOpen);
+        let straight = find_path(&open, &TileCosts::default(), (0, 0), (4, 0), &settings).unwrap();
+        assert_eq!(straight, [(0, 0), (1, 0), (2, 0), (3, 0), (4, 0)]);
+
+        let mud = [(1, 0), (2, 0), (3, 0)];
+        let costs = TileCosts(mud.iter().map(|&c| (c, 5.0)).collect());
+        let detour = find_path(&open, &costs, (0, 0), (4, 0), &settings).unwrap();
+        assert_eq!(detour.len(), 7, "{detour:?}");
+        assert!(detour.iter().all(|c| !mud.contains(c)), "through the mud: {detour:?}");
+
+        // Cheaper than the detour: straight through.
+        let costs = TileCosts(mud.iter().map(|&c| (c, 1.5)).collect());
+        assert_eq!(find_path(&open, &costs, (0, 0), (4, 0), &settings).unwrap(), straight);
+    }
+
+    #[test]
+    fn unreachable_goals_have_no_path() {
+        let costs = TileCosts::default();
+        let settings = PathSettings::default();
+        assert_eq!(find_path(&walls(&[(5, 5)]), &costs, (0, 0), (5, 5), &settings), None);
+
+        // Walled in: the search only stops at `max_expansions`.
+        let ring: Vec<(i32, i32)> = (-1..=1)
+            .flat_map(|dx| (-1..=1).map(move |dy| (5 + dx, 5 + dy)))
+            .filter(|&c| c != (5, 5))
+            .collect();
+        assert_eq!(find_path(&walls(&ring), &costs, (0, 0), (5, 5), &settings), None);
+    }
+
+    #[test]
+    fn searches_give_up_after_max_expansions() {
+        let blocked = walls(&[]);
+        let costs = TileCosts::default();
+        let settings = PathSettings { max_expansions: 10, ..default() };
+        assert_eq!(find_path(&blocked, &costs, (0, 0), (30, 0), &settings), None);
+        assert_eq!(find_path(&blocked, &costs, (0, 0), (5, 0), &settings).map(|p| p.len()), Some(6));
+        let path = find_path(&blocked, &costs, (0, 0), (30, 0), &PathSettings::default());
+        assert_eq!(path.map(|p| p.len()), Some(31));
+    }
+
+    /// A test app walking the local player along `PathFollow`s at `ticks_per_frame`.
+    fn walker(blocked: world::Blocked, search: PathSettings, ticks_per_frame: f64) -> App {
+        let mut app = App::new();
+        let frame = Duration::from_secs_f64(ticks_per_frame / constants::TICK_HZ);
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(frame))
+            .insert_resource(Time::<Fixed>::from_hz(constants::TICK_HZ))
+            .insert_resource(blocked)
+            .init_resource::<TileCosts>()
+            .insert_resource(ClickToMove { search, ..default() })
+            .add_systems(FixedUpdate, (follow_path, collision::move_with_collision_system).chain());
+        app
+    }
+
+    fn spawn_walker(app: &mut App, at: (i32, i32), path: PathFollow) -> Entity {
+        app.world_mut().spawn((
+            world::LocalPlayer,
+            world::GridPos { x: at.0 as f32, y: at.1 as f32 },
+            collision::MoveIntent::default(),
+            path,
+        )).id()
+    }
+
+    fn assert_arrived(app: &App, player: Entity, goal: (i32, i32), what: &str) {
+        let player = app.world().entity(player);
+        assert!(!player.contains::<PathFollow>(), "{what}: the path never completed");
+        let gp = player.get::<world::GridPos>().unwrap();
+        let goal = Vec2::new(goal.0 as f32, goal.1 as f32);
+        assert!(Vec2::new(gp.x, gp.y).abs_diff_eq(goal, 1e-3), "{what}: stopped at {gp:?}");
+    }
+
+    #[test]
+    fn paths_complete_at_low_frame_rates() {
+        // 20 FPS: three fixed ticks per frame.
+        let mut app = walker(walls(&[]), PathSettings::default(), 3.0);
+        let player = spawn_walker(&mut app, (0, 0), PathFollow::new([(1, 0), (2, 0), (2, 1), (3, 2)]));
+        for _ in 0..60 { app.update(); }
+        assert_arrived(&app, player, (3, 2), "20 FPS");
+    }
+
+    #[test]
+    fn movers_get_past_corners_in_every_mode() {
+        let layouts = [walls(&[(1, 0)]), walls(&[(1, 0), (0, 1)]), walls(&[(1, 0), (0, 1), (2, 1), (1, 2)])];
+        for corners in [CornerRule::Always, CornerRule::OneOpen, CornerRule::BothOpen] {
+            for (i, blocked) in layouts.iter().enumerate() {
+                let settings = search(Connectivity::Eight, corners);
+                let path = find_path(blocked, &TileCosts::default(), (0, 0), (2, 2), &settings).unwrap();
+                let mut app = walker(world::Blocked(blocked.0.clone()), settings, 1.0);
+                let player = spawn_walker(&mut app, (0, 0), PathFollow::new(path.into_iter().skip(1)));
+                for _ in 0..600 { app.update(); }
+                assert_arrived(&app, player, (2, 2), &format!("{corners:?} in layout {i}"));
+            }
+        }
+    }
+
+    #[test]
+    fn movers_give_up_when_boxed_in() {
+        // Only a diagonal way out, which `try_move` can't squeeze through.
+        let box_walls = [(1, 0), (-1, 0), (0, 1), (0, -1), (-1, -1), (1, -1), (-1, 1)];
+        let mut app = walker(walls(&box_walls), search(Connectivity::Eight, CornerRule::Always), 1.0);
+        let player = spawn_walker(&mut app, (0, 0), PathFollow::new([(1, 1), (2, 2)]));
+        for _ in 0..120 { app.update(); }
+        let player = app.world().entity(player);
+        assert!(!player.contains::<PathFollow>(), "still pushing against the corner");
+        assert_eq!(player.get::<collision::MoveIntent>().unwrap().vel, Vec2::ZERO);
+    }
+}
@@ -0,0 +1,178 @@
+//! Pings: an alt-click on the minimap (see `minimap::click_minimap`) marks a
+//! tile for a few seconds, for us and for our teammates, who hear about it
+//! through the server the same way they hear about explored tiles (see `fog`).
+//! A ping shows as a pulsing ring on the minimap and on the ground.
+use bevy::prelude::*;
+use crate::camera::MinimapCamera;
+use crate::constants::{TILE_H, TILE_W};
+use crate::minimap::{self, MinimapFrame};
+use crate::world;
+
+/// How long a ping stays up.
+pub const PING_SECONDS: f32 = 4.0;
+/// Ring size on the minimap, in UI pixels, at the start of each pulse.
+const RING_SIZE: f32 = 26.0;
+const RING_MIN_SIZE: f32 = 8.0;
+// Ours in the local player's gold (see `minimap`), teammates' in cyan.
+const OUR_PING: Color = Color::srgb(0.95, 0.8, 0.3);
+const TEAM_PING: Color = Color::srgb(0.3, 0.9, 0.9);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ping {
+    pub id: u32,
+    pub cell: (i32, i32),
+    /// Placed by us rather than a teammate.
+    pub ours: bool,
+    /// Seconds until it goes away.
+    pub left: f32,
+}
+
+impl Ping {
+    /// Goes from 1 to 0 once a second, for the pulse.
+    fn pulse(&self) -> f32 {
+        self.left.fract()
+    }
+
+    fn color(&self) -> Color {
+        if self.ours { OUR_PING } else { TEAM_PING }
+    }
+}
+
+/// The pings up at the moment.
+#[derive(Resource, Debug, Default)]
+pub struct Pings {
+    pub active: Vec<Ping>,
+    next_id: u32,
+    /// Tiles we pinged that our team hasn't heard about yet.
+    pub unshared: Vec<(i32, i32)>,
+}
+
+impl Pings {
+    pub fn add(&mut self, cell: (i32, i32), ours: bool) {
+        self.next_id += 1;
+        self.active.push(Ping { id: self.next_id, cell, ours, left: PING_SECONDS });
+    }
+
+    /// A ping of ours, queued for our team if we are on one.
+    pub fn place(&mut self, cell: (i32, i32), share: bool) {
+        self.add(cell, true);
+        if share {
+            self.unshared.push(cell);
+        }
+    }
+
+    /// Ages the pings by `dt` seconds, dropping those that ran out.
+    pub fn tick(&mut self, dt: f32) {
+        for ping in &mut self.active {
+            ping.left -= dt;
+        }
+        self.active.retain(|ping| ping.left > 0.0);
+    }
+}
+
+/// The ring of ping `id` on the minimap.
+#[derive(Component, Debug)]
+pub struct PingMarker {
+    pub id: u32,
+}
+
+pub fn expire_pings(time: Res<Time>, mut pings: ResMut<Pings>) {
+    if pings.active.is_empty() { return; }
+    pings.tick(time.delta_secs());
+}
+
+/// Gives each ping a ring on the minimap, pinned to the edge if the tile is
+/// off it, and drops the rings of pings that ran out.
+pub fn place_ping_markers(
+    mut commands: Commands,
+    pings: Res<Pings>,
+    frame: Query<Entity, With<MinimapFrame>>,
+    cam_q: Query<(&Camera, &Transform), With<MinimapCamera>>,
+    mut markers: Query<(Entity, &PingMarker, &mut Node, &mut Visibility)>,
+) {
+    let Ok(frame) = frame.single() else { return };
+    let Ok((cam, cam_t)) = cam_q.single() else { return };
+    for (e, marker, mut node, mut visibility) in &mut markers {
+        let Some(ping) = pings.active.iter().find(|p| p.id == marker.id) else {
+            commands.entity(e).despawn();
+            continue;
+        };
+        let world = world::grid_to_iso(ping.cell.0 as f32, ping.cell.1 as f32, TILE_W, TILE_H);
+        let Some(at) = minimap::world_to_minimap(cam, cam_t, world) else { continue };
+        let size = RING_MIN_SIZE.lerp(RING_SIZE, ping.pulse());
+        let (at, _) = minimap::clamp_to_minimap(at, RING_MIN_SIZE / 2.0);
+        node.left = Val::Px(at.x - size / 2.0);
+        node.top = Val::Px(at.y - size / 2.0);
+        node.width = Val::Px(size);
+        node.height = Val::Px(size);
+        visibility.set_if_neq(Visibility::Inherited);
+    }
+    for ping in &pings.active {
+        if markers.iter().any(|(_, marker, ..)| marker.id == ping.id) { continue; }
+        commands.entity(frame).with_child((
+            PingMarker { id: ping.id },
+            Node {
+                position_type: PositionType::Absolute,
+                border: UiRect::all(Val::Px(2.0)),
+                ..default()
+            },
+            BorderColor::all(ping.color()),
+            BorderRadius::MAX,
+            // Over the player icons.
+            ZIndex(3),
+            // Placed (and shown) next frame.
+            Visibility::Hidden,
+        ));
+    }
+}
+
+/// The same rings on the ground, around the pinged tiles.
+pub fn draw_pings(mut gizmos: Gizmos, pings: Res<Pings>) {
+    for ping in &pings.active {
+        let mut center = world::grid_to_iso(ping.cell.0 as f32, ping.cell.1 as f32, TILE_W, TILE_H);
+        center.y = 0.02;
+        let radius = 0.15_f32.lerp(0.6, ping.pulse());
+        gizmos.circle(
+            Isometry3d::new(center, Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2)),
+            radius,
+            ping.color(),
+        );
+    }
+}
+
+/// `OnExit(AppState::InGame)`: pings don't outlive the game.
+pub fn clear_pings(mut pings: ResMut<Pings>) {
+    *pings = Pings::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pings_run_out() {
+        let mut pings = Pings::default();
+        pings.add((3, 4), true);
+        pings.tick(PING_SECONDS - 1.0);
+        pings.add((5, 6), false);
+        assert_eq!(pings.active.len(), 2);
+        assert_ne!(pings.active[0].id, pings.active[1].id);
+
+        pings.tick(1.5);
+        assert_eq!(pings.active.len(), 1);
+        assert_eq!(pings.active[0].cell, (5, 6));
+        assert!(!pings.active[0].ours);
+    }
+
+    #[test]
+    fn only_our_pings_go_to_the_team() {
+        let mut pings = Pings::default();
+        pings.place((1, 1), false);
+        assert!(pings.unshared.is_empty(), "no team, nobody to tell");
+        pings.place((2, 2), true);
+        pings.add((3, 3), false);
+        assert_eq!(pings.unshared, [(2, 2)], "a teammate's ping is not sent back");
+        let ours: Vec<bool> = pings.active.iter().map(|p| p.ours).collect();
+        assert_eq!(ours, [true, true, false]);
+    }
+}
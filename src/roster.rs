@@ -0,0 +1,214 @@
+//! The playable characters: one per portrait in `players2/` (next to `assets/`,
+//! loaded through the `players://` asset source). `Juan.png` and `Juan2.png` are
+//! two looks of the same character, Juan; trailing digits number the variant.
+//!
+//! Each player's choice travels as an [`Avatar`]: picked in the Lobby screen,
+//! put on the local player, and sent to the server, which tells everyone else.
+use bevy::{asset::io::file::FileAssetReader, prelude::*};
+use serde::{Deserialize, Serialize};
+
+/// Where the portraits live, relative to the asset base path (the crate root).
+pub const PORTRAIT_DIR: &str = "players2";
+/// Asset source serving [`PORTRAIT_DIR`]: `players://Juan2.png`.
+pub const PORTRAIT_SOURCE: &str = "players";
+/// Longer names from the network are cut short.
+pub const MAX_NAME_LEN: usize = 32;
+
+/// Who a player is: a character and which of its portraits. Goes over the
+/// wire (see `net::protocol`); other clients look it up in their own roster.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Avatar {
+    pub name: String,
+    /// 1 for `Juan.png`, 2 for `Juan2.png`, ...
+    pub variant: u32,
+}
+
+impl Avatar {
+    /// `Juan2.png` -> Juan, variant 2. Anything but a `.png` is not a portrait.
+    pub fn from_file(file: &str) -> Option<Self> {
+        let stem = file.strip_suffix(".png").or_else(|| file.strip_suffix(".PNG"))?;
+        let name = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+        if name.is_empty() {
+            return None;
+        }
+        let variant = stem[name.len()..].parse().unwrap_or(1);
+        Some(Self { name: name.to_string(), variant })
+    }
+
+    /// What the server keeps of an avatar a client sent.
+    pub fn sanitized(mut self) -> Self {
+        if let Some((cut, _)) = self.name.char_indices().nth(MAX_NAME_LEN) {
+            self.name.truncate(cut);
+        }
+        self
+    }
+}
+
+impl std::fmt::Display for Avatar {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.variant {
+            0 | 1 => write!(f, "{}", self.name),
+            v => write!(f, "{} ({v})", self.name),
+        }
+    }
+}
+
+/// One portrait file.
+#[derive(Clone, Debug)]
+pub struct Portrait {
+    pub variant: u32,
+    pub file: String,
+    pub image: Handle<Image>,
+}
+
+/// A character and its portraits, lowest variant first.
+#[derive(Clone, Debug)]
+pub struct Character {
+    pub name: String,
+    pub portraits: Vec<Portrait>,
+}
+
+/// Every character found in [`PORTRAIT_DIR`], by name.
+#[derive(Resource, Debug, Default)]
+pub struct Roster {
+    pub characters: Vec<Character>,
+}
+
+impl Roster {
+    /// Groups portrait file names into characters (image handles left empty).
+    pub fn from_files<'a>(files: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut characters: Vec<Character> = Vec::new();
+        for file in files {
+            let Some(avatar) = Avatar::from_file(file) else { continue };
+            let portrait = Portrait { variant: avatar.variant, file: file.to_string(), image: default() };
+            match characters.iter_mut().find(|c| c.name == avatar.name) {
+                Some(c) => c.portraits.push(portrait),
+                None => characters.push(Character { name: avatar.name, portraits: vec![portrait] }),
+            }
+        }
+        characters.sort_by(|a, b| a.name.cmp(&b.name));
+        for c in &mut characters {
+            c.portraits.sort_by_key(|p| p.variant);
+        }
+        Self { characters }
+    }
+
+    pub fn portrait(&self, avatar: &Avatar) -> Option<&Portrait> {
+        self.characters
+            .iter()
+            .find(|c| c.name == avatar.name)?
+            .portraits
+            .iter()
+            .find(|p| p.variant == avatar.variant)
+    }
+
+    /// The first look of the `index`th character.
+    pub fn avatar(&self, index: usize) -> Option<Avatar> {
+        let c = self.characters.get(index)?;
+        Some(Avatar { name: c.name.clone(), variant: c.portraits.first()?.variant })
+    }
+
+    /// `avatar`'s next look, wrapping around to its first.
+    pub fn next_variant(&self, avatar: &Avatar) -> Avatar {
+        let Some(c) = self.characters.iter().find(|c| c.name == avatar.name) else { return avatar.clone() };
+        let next = c
+            .portraits
+            .iter()
+            .find(|p| p.variant > avatar.variant)
+            .or(c.portraits.first())
+            .map_or(avatar.variant, |p| p.variant);
+        Avatar { name: c.name.clone(), variant: next }
+    }
+}
+
+/// Who we play as. Set in the Lobby screen; `--avatar Juan2` picks up front.
+#[derive(Resource, Clone, Debug, PartialEq, Eq)]
+pub struct ChosenAvatar(pub Avatar);
+
+/// Startup: finds the portraits, starts loading them, and picks a character
+/// for us (`--avatar`, else any, so that two players rarely look alike).
+pub fn load_roster(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let dir = FileAssetReader::get_base_path().join(PORTRAIT_DIR);
+    let files: Vec<String> = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok()?.file_name().into_string().ok())
+            .collect(),
+        Err(err) => {
+            warn!("no portraits in {}: {err}", dir.display());
+            Vec::new()
+        }
+    };
+    let mut roster = Roster::from_files(files.iter().map(String::as_str));
+    for c in &mut roster.characters {
+        for p in &mut c.portraits {
+            p.image = asset_server.load(format!("{PORTRAIT_SOURCE}://{}", p.file));
+        }
+    }
+    info!("{} characters in the roster", roster.characters.len());
+
+    let asked = crate::net::arg_value("--avatar")
+        .and_then(|a| Avatar::from_file(&format!("{a}.png")))
+        .filter(|a| roster.portrait(a).is_some());
+    let any = (crate::net::now().subsec_nanos() as usize).checked_rem(roster.characters.len());
+    let chosen = asked
+        .or_else(|| roster.avatar(any?))
+        .unwrap_or(Avatar { name: "Player".into(), variant: 1 });
+    commands.insert_resource(ChosenAvatar(chosen));
+    commands.insert_resource(roster);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn portraits_group_into_characters() {
+        let roster = Roster::from_files(["Juan2.png", "Marc.png", "Juan.png", "MarcBig.png", "notes.txt", "7.png"]);
+        let names: Vec<&str> = roster.characters.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["Juan", "Marc", "MarcBig"]);
+        let juan = &roster.characters[0];
+        assert_eq!(juan.portraits.iter().map(|p| p.variant).collect::<Vec<_>>(), [1, 2]);
+
+        let juan2 = Avatar { name: "Juan".into(), variant: 2 };
+        assert_eq!(roster.portrait(&juan2).unwrap().file, "Juan2.png");
+        assert_eq!(juan2.to_string(), "Juan (2)");
+        assert_eq!(roster.next_variant(&juan2), roster.avatar(0).unwrap());
+        assert!(roster.portrait(&Avatar { name: "Nobody".into(), variant: 1 }).is_none());
+    }
+
+    #[test]
+    fn long_names_are_cut() {
+        let avatar = Avatar { name: "é".repeat(100), variant: 1 }.sanitized();
+        assert_eq!(avatar.name.chars().count(), MAX_NAME_LEN);
+    }
+
+    #[test]
+    fn file_names_give_name_and_variant() {
+        let avatar = |file| Avatar::from_file(file).map(|a| (a.name, a.variant));
+        assert_eq!(avatar("Juan.png"), Some(("Juan".into(), 1)));
+        assert_eq!(avatar("Juan12.PNG"), Some(("Juan".into(), 12)));
+        assert_eq!(avatar("R2D2.png"), Some(("R2D".into(), 2)), "only trailing digits count");
+        assert_eq!(avatar("7.png"), None);
+        assert_eq!(avatar("Juan.jpg"), None);
+    }
+
+    #[test]
+    fn names_show_the_variant_past_the_first() {
+        assert_eq!(Avatar { name: "Marc".into(), variant: 1 }.to_string(), "Marc");
+        assert_eq!(Avatar { name: "Marc".into(), variant: 3 }.to_string(), "Marc (3)");
+    }
+
+    #[test]
+    fn variants_cycle() {
+        let roster = Roster::from_files(["Ana3.png", "Ana.png", "Ana7.png"]);
+        let ana = |variant| Avatar { name: "Ana".into(), variant };
+        assert_eq!(roster.avatar(0), Some(ana(1)));
+        assert_eq!(roster.next_variant(&ana(1)), ana(3));
+        assert_eq!(roster.next_variant(&ana(3)), ana(7));
+        assert_eq!(roster.next_variant(&ana(7)), ana(1));
+        // Avatars we have no portraits for (another client's roster) stay as they are.
+        let stranger = Avatar { name: "Zed".into(), variant: 2 };
+        assert_eq!(roster.next_variant(&stranger), stranger);
+        assert_eq!(roster.avatar(1), None);
+    }
+}
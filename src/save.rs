@@ -0,0 +1,74 @@
+//! Save games: one `.save.ron` per level under `saves/` (next to where the
+//! game runs). Written on F5 and when leaving the game, read back when the
+//! level is built. For now they remember what the player explored (see `fog`).
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use crate::fog::FogOfWar;
+use crate::level;
+
+/// Where save games go, relative to the working directory.
+pub const SAVE_DIR: &str = "saves";
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct SaveGame {
+    pub width: usize,
+    pub height: usize,
+    /// Explored tiles, one string per row: `#` explored, `.` not.
+    pub explored: Vec<String>,
+}
+
+impl SaveGame {
+    pub fn path(level: &str) -> PathBuf {
+        PathBuf::from(SAVE_DIR).join(format!("{level}.save.ron"))
+    }
+
+    /// The save for `level`, if there is a readable one.
+    pub fn load(level: &str) -> Option<Self> {
+        let path = Self::path(level);
+        let text = std::fs::read_to_string(&path).ok()?;
+        match ron::from_str(&text) {
+            Ok(save) => Some(save),
+            Err(err) => {
+                warn!("ignoring {}: {err}", path.display());
+                None
+            }
+        }
+    }
+
+    pub fn store(&self, level: &str) -> Result<PathBuf, String> {
+        let path = Self::path(level);
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).map_err(|e| e.to_string())?;
+        std::fs::create_dir_all(SAVE_DIR).map_err(|e| e.to_string())?;
+        std::fs::write(&path, text).map_err(|e| e.to_string())?;
+        Ok(path)
+    }
+
+    pub fn from_fog(fog: &FogOfWar) -> Self {
+        Self { width: fog.width, height: fog.height, explored: fog.explored_rows() }
+    }
+}
+
+fn save(current: &level::CurrentLevel, fog: &FogOfWar) {
+    if fog.width == 0 { return; }
+    match SaveGame::from_fog(fog).store(&current.name()) {
+        Ok(path) => info!("saved to {}", path.display()),
+        Err(err) => error!("can't save: {err}"),
+    }
+}
+
+/// F5 saves.
+pub fn quick_save(keys: Res<ButtonInput<KeyCode>>, current: Option<Res<level::CurrentLevel>>, fog: Res<FogOfWar>) {
+    if !keys.just_pressed(KeyCode::F5) { return; }
+    if let Some(current) = current {
+        save(&current, &fog);
+    }
+}
+
+/// `OnExit(AppState::InGame)`: saves, then forgets the level's fog.
+pub fn save_on_exit(current: Option<Res<level::CurrentLevel>>, mut fog: ResMut<FogOfWar>) {
+    if let Some(current) = current {
+        save(&current, &fog);
+    }
+    *fog = FogOfWar::default();
+}
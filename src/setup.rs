@@ -1,38 +1,111 @@
 use bevy::{
     prelude::*,
-    camera::{ScalingMode, RenderTarget},
+    camera::{ScalingMode, RenderTarget, visibility::RenderLayers},
+    ui::RelativeCursorPosition,
     render::{            
             render_resource::{
             Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
         }
     },
 };
+use bevy_egui::PrimaryEguiContext;
+use crate::billboard;
 use crate::constants;
+use crate::fog;
 use crate::world;
 use crate::camera;
+use crate::collision;
+use crate::minimap;
+use crate::roster;
+use crate::states::AppState;
+
+/// The player this client controls, as the character picked in the Lobby.
+/// Simulation only; `player_visuals` gives it a card to be seen by.
+pub fn player(mut commands: Commands, chosen: Option<Res<roster::ChosenAvatar>>) {
+    let start = world::GridPos { x: 0.0, y: 0.0 };
+    let p = world::grid_to_iso(start.x, start.y, constants::TILE_W, constants::TILE_H);
+    let e = commands.spawn((
+        DespawnOnExit(AppState::InGame),
+        world::LocalPlayer,
+        collision::MoveIntent::default(),
+        start,
+        Transform::from_translation(p),
+    )).id();
+    if let Some(chosen) = chosen {
+        commands.entity(e).insert(chosen.0.clone());
+    }
+    if let Some(team) = world::Team::from_args() {
+        commands.entity(e).insert(team);
+    }
+}
 
-pub fn scene(
+/// Gives every new player (ours or remote) a portrait card standing on a
+/// coloured disc. The card gets its picture in `billboard::show_portraits`;
+/// the disc is what the top-down minimap sees.
+pub fn player_visuals(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    mut blocked: ResMut<world::Blocked>,
+    new: Query<(Entity, Has<world::LocalPlayer>), Added<world::GridPos>>,
 ) {
+    // As wide as the player's footprint, so it never pokes into a wall.
+    let size = 2.0 * constants::MOVER_HALF;
+    for (e, local) in &new {
+        let color = if local { Color::srgb(0.8, 0.8, 0.5) } else { Color::srgb(0.4, 0.6, 0.9) };
+        let card = materials.add(StandardMaterial {
+            base_color: color,
+            alpha_mode: AlphaMode::Mask(0.5),
+            unlit: true,
+            cull_mode: None,
+            ..default()
+        });
+        // The player's transform is at cube height (y = 0.5); stand on the ground.
+        commands.entity(e).insert(Visibility::default()).with_children(|p| {
+            p.spawn((
+                billboard::Billboard,
+                billboard::PortraitCard,
+                // Origin at the bottom edge, so `face_iso_camera`'s stretch grows it upward.
+                Mesh3d(meshes.add(Rectangle::new(size, size).mesh().build().translated_by(Vec3::Y * size / 2.0))),
+                MeshMaterial3d(card),
+                Transform::from_xyz(0.0, -0.5, 0.0),
+            ));
+            p.spawn((
+                Mesh3d(meshes.add(Circle::new(size / 2.0))),
+                MeshMaterial3d(materials.add(color)),
+                Transform::from_xyz(0.0, 0.01 - 0.5, 0.0)
+                    .with_rotation(Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2)),
+            ));
+        });
+    }
+}
+
+pub fn scene(mut commands: Commands) {
 
     let yaw = 45.0;
     let pitch = 35.264; // classic isometric tilt
     let radius = 10.0; // distance from origin    
+    let zoom = 10.0; // visible world height
 
     // --- Camera: orthographic + isometric angle ---
     commands.spawn((
+        DespawnOnExit(AppState::InGame),
         Camera3d::default(),
         // Orthographic projection (no perspective)
         Projection::from(OrthographicProjection {
             // Keep a fixed vertical world size; tweak to your liking
-            scaling_mode: ScalingMode::FixedVertical { viewport_height: 10.0 },
+            scaling_mode: ScalingMode::FixedVertical { viewport_height: zoom },
             ..OrthographicProjection::default_3d()
         }),
         camera::iso_camera_transform(yaw, pitch, radius),
-        camera::IsoCamera { yaw_deg: yaw, pitch_deg: pitch, radius },
+        camera::IsoCamera {
+            yaw_deg: yaw,
+            pitch_deg: pitch,
+            radius,
+            zoom,
+            zoom_target: zoom,
+            zoom_min: 4.0,
+            zoom_max: 30.0,
+        },
         camera::CameraSpin {
             start_yaw: yaw,
             end_yaw: yaw,
@@ -40,65 +113,15 @@ pub fn scene(
             duration: 0.35,     // tweak for snappier/slower spin
             queued_steps: 0,
         },
-        camera::CameraFollow { stiffness: 20.0, damping: 10.0, vel: Vec3::ZERO },
+        camera::CameraFollow::critically_damped(20.0),
+        // Debug panels (egui) draw on the main view.
+        PrimaryEguiContext,
         // Put camera on a diagonal and look at the origin.
         // Using equal XYZ like (10,10,10) gives a classic iso feel (~45° around Y, ~35.264° tilt).
         // Transform::from_xyz(10.0, 10.0, 10.0).looking_at(Vec3::ZERO, Vec3::Y),
     ));
 
-    /// Example level data (replace with your parsed CSV/JSON/etc.)
-    const W: usize = 10;
-    const H: usize = 8;
-    const LEVEL: [[u8; W]; H] = [
-        [1,1,1,0,0,0,0,0,0,0],
-        [1,0,0,0,0,0,0,0,0,0],
-        [0,0,0,0,0,0,0,0,0,0],
-        [0,0,0,0,0,0,0,0,0,0],
-        [0,0,0,0,0,0,0,0,0,0],
-        [0,0,0,0,0,0,0,0,0,0],
-        [0,0,0,0,0,0,0,0,0,0],
-        [0,0,0,0,0,0,0,0,0,0],
-    ];
-
-    blocked.0.clear();
-    for gy in 0..H {
-        for gx in 0..W {
-            if LEVEL[gy][gx] == 1 {
-                // blocked.0.insert((gx as i32, gy as i32));
-            }
-        }
-    }
-
-    // Example walls (blocked cells)
-    // blocked.0.extend([(1, 0)]); // add any cells you want to be solid
-    // blocked.0.extend([(2, 0)]); // add any cells you want to be solid
-    // blocked.0.extend([(3, 0)]); // add any cells you want to be solid
-    // blocked.0.extend([(0, -1)]);
-    // blocked.0.extend([(0, -2)]);
-    // blocked.0.extend([(0, -3)]);
-
-
-    // Visualize blocked cells
-    for &(x, y) in &blocked.0 {                
-        let p = world::grid_to_iso(x as f32, y as f32, constants::TILE_W, constants::TILE_H);
-        
-        commands.spawn((
-            world::Solid,
-            Mesh3d(meshes.add(Cuboid::new(1.0, 1.0, 1.0))),
-            MeshMaterial3d(materials.add(Color::srgb(0.7, 0.4, 0.5))),            
-            Transform::from_translation(p),
-        ));        
-    }
-
-    // The movable player cube
-    let start = world::GridPos { x: 0.0, y: 0.0 };    
-    let p = world::grid_to_iso(start.x, start.y, constants::TILE_W, constants::TILE_H);
-    commands.spawn((
-        start,
-        Mesh3d(meshes.add(Cuboid::new(1.0, 1.0, 1.0))),
-        MeshMaterial3d(materials.add(Color::srgb(0.8, 0.8, 0.5))),
-        Transform::from_translation(p),
-    ));
+    // Walls, props and the player's spawn point come from the level file (see `level.rs`).
 
     // --- Quick scene to verify ---
     // commands.spawn((
@@ -108,6 +131,7 @@ pub fn scene(
 
     // Light
     commands.spawn((
+        DespawnOnExit(AppState::InGame),
         world::FollowLight,
         PointLight::default(), 
         Transform::from_xyz(6.0, 10.0, 6.0)
@@ -143,12 +167,14 @@ pub fn minimap(
     // 2) Minimap camera: top-down orthographic
     //    - Look straight down from +Y onto the XZ plane.
     //    - Up vector = -Z so that +X is right and +Z is down on the minimap (Cartesian screen look).
+    let viewport_height = 20.0;
     let ortho = OrthographicProjection {
         // Choose a fixed world height; bigger => more area visible
-        scaling_mode: ScalingMode::FixedVertical { viewport_height: 20.0 },
+        scaling_mode: ScalingMode::FixedVertical { viewport_height },
         ..OrthographicProjection::default_3d()
     };
     commands.spawn((
+        DespawnOnExit(AppState::InGame),
         Camera3d::default(),
         Camera {
             // Render into the texture instead of the screen
@@ -159,21 +185,27 @@ pub fn minimap(
         // Place camera above origin looking down
         Transform::from_translation(Vec3::new(0.0, 50.0, 0.001)) // small z offset to avoid singular up vector
             .looking_at(Vec3::ZERO, -Vec3::Z), // up = -Z gives Cartesian feel on the image
-        // If you want to show extra overlays only on minimap:
-        // RenderLayers::from_layers(&[0, 1]),
-        camera::MinimapCamera { height: 50.0, center: Vec3::ZERO },
-        camera::CameraFollow { stiffness: 20.0, damping: 10.0, vel: Vec3::ZERO }
+        // The fog of war is drawn only on the minimap.
+        RenderLayers::from_layers(&[0, fog::FOG_LAYER]),
+        camera::MinimapCamera {
+            height: 50.0,
+            center: Vec3::ZERO,
+            viewport_height,
+            zoom_ratio: Some(2.0), // twice the main view; None = fixed
+        },
+        camera::CameraFollow::critically_damped(20.0),
     ));
 
     // 3) UI: place the render texture in the corner
-    commands.spawn(Node {
-        width:Val::Px(180.0),
-        height: Val::Px(180.0),        
+    // Player icons and pings go on top of it (see `minimap.rs`); it can be clicked.
+    commands.spawn((DespawnOnExit(AppState::InGame), minimap::MinimapFrame, RelativeCursorPosition::default(), Node {
+        width:Val::Px(minimap::UI_SIZE),
+        height: Val::Px(minimap::UI_SIZE),        
         position_type: PositionType::Absolute,
         right: Val::Px(10.0),
         top: Val::Px(10.0),
         ..default()
-    })
+    }))
     .with_children(|parent| {
         parent.spawn(ImageNode {            
             image: rt_handle,
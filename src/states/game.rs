@@ -0,0 +1,17 @@
+use bevy::prelude::*;
+use super::AppState;
+
+/// Shown while `level::wait_for_level` waits on the asset server.
+pub fn spawn_loading_screen(mut commands: Commands) {
+    commands.spawn(super::ui_camera(AppState::Loading));
+    commands.spawn(super::screen(AppState::Loading)).with_children(|p| {
+        p.spawn(super::label("Loading..."));
+    });
+}
+
+/// Esc leaves the game; everything tagged `DespawnOnExit(InGame)` goes with it.
+pub fn leave_game(keys: Res<ButtonInput<KeyCode>>, mut next: ResMut<NextState<AppState>>) {
+    if keys.just_pressed(KeyCode::Escape) {
+        next.set(AppState::Menu);
+    }
+}
@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+use bevy_egui::PrimaryEguiContext;
+use crate::net::platform::{ActivePlatform, LobbyStatusText};
+use crate::roster::{ChosenAvatar, Roster};
+use super::{AppState, MenuAction};
+
+const PORTRAIT_SIZE: f32 = 56.0;
+const PORTRAIT_IDLE: Color = Color::srgb(0.2, 0.2, 0.25);
+const PORTRAIT_CHOSEN: Color = Color::srgb(0.95, 0.8, 0.3);
+
+/// A portrait in the character select; the index into `Roster::characters`.
+#[derive(Component)]
+pub struct CharacterButton(pub usize);
+
+/// Marks the "Playing as ..." text.
+#[derive(Component)]
+pub struct ChosenAvatarText;
+
+pub fn spawn_lobby(mut commands: Commands, platform: Option<Res<ActivePlatform>>, roster: Res<Roster>) {
+    // egui draws the LAN server browser (`net::discovery`) on this camera.
+    commands.spawn((super::ui_camera(AppState::Lobby), PrimaryEguiContext));
+    commands.spawn(super::screen(AppState::Lobby)).with_children(|p| {
+        p.spawn(super::title("Lobby"));
+        if platform.is_some() {
+            // Filled in by `net::platform::update_lobby_status`.
+            p.spawn((super::label(""), LobbyStatusText));
+        }
+        // Character select: click a portrait, click it again for its other looks.
+        p.spawn(Node {
+            max_width: Val::Px(10.0 * (PORTRAIT_SIZE + 6.0)),
+            flex_wrap: FlexWrap::Wrap,
+            justify_content: JustifyContent::Center,
+            column_gap: Val::Px(6.0),
+            row_gap: Val::Px(6.0),
+            ..default()
+        }).with_children(|grid| {
+            for (i, c) in roster.characters.iter().enumerate() {
+                let Some(first) = c.portraits.first() else { continue };
+                grid.spawn((
+                    Button,
+                    CharacterButton(i),
+                    Node {
+                        width: Val::Px(PORTRAIT_SIZE),
+                        height: Val::Px(PORTRAIT_SIZE),
+                        border: UiRect::all(Val::Px(3.0)),
+                        ..default()
+                    },
+                    BorderColor::all(PORTRAIT_IDLE),
+                    ImageNode::new(first.image.clone()),
+                ));
+            }
+        });
+        // Filled in by `show_chosen_character`.
+        p.spawn((super::label(""), ChosenAvatarText));
+        p.spawn(super::button("Start", MenuAction::Goto(AppState::Loading)));
+        p.spawn(super::button("Back", MenuAction::Goto(AppState::Menu)));
+    });
+}
+
+/// Enter = Start, Esc = Back.
+pub fn lobby_keys(keys: Res<ButtonInput<KeyCode>>, mut next: ResMut<NextState<AppState>>) {
+    if keys.just_pressed(KeyCode::Enter) {
+        next.set(AppState::Loading);
+    } else if keys.just_pressed(KeyCode::Escape) {
+        next.set(AppState::Menu);
+    }
+}
+
+/// Clicking a portrait picks that character; clicking the picked one again
+/// cycles through its variants (`Juan.png`, `Juan2.png`, ...).
+pub fn pick_character(
+    q: Query<(&Interaction, &CharacterButton), Changed<Interaction>>,
+    roster: Res<Roster>,
+    mut chosen: ResMut<ChosenAvatar>,
+) {
+    for (interaction, button) in &q {
+        if *interaction != Interaction::Pressed { continue; }
+        let Some(c) = roster.characters.get(button.0) else { continue };
+        chosen.0 = if c.name == chosen.0.name {
+            roster.next_variant(&chosen.0)
+        } else {
+            roster.avatar(button.0).unwrap_or_else(|| chosen.0.clone())
+        };
+    }
+}
+
+/// Frames the picked character, shows the picked variant on its button,
+/// and says who we play as.
+pub fn show_chosen_character(
+    roster: Res<Roster>,
+    chosen: Res<ChosenAvatar>,
+    mut buttons: Query<(&CharacterButton, &mut BorderColor, &mut ImageNode)>,
+    mut text: Query<&mut Text, With<ChosenAvatarText>>,
+) {
+    for (button, mut border, mut image) in &mut buttons {
+        let Some(c) = roster.characters.get(button.0) else { continue };
+        let picked = c.name == chosen.0.name;
+        let color = if picked { PORTRAIT_CHOSEN } else { PORTRAIT_IDLE };
+        if border.top != color {
+            *border = BorderColor::all(color);
+        }
+        let shown = if picked { roster.portrait(&chosen.0) } else { c.portraits.first() };
+        if let Some(shown) = shown.filter(|p| p.image != image.image) {
+            image.image = shown.image.clone();
+        }
+    }
+    let Ok(mut text) = text.single_mut() else { return };
+    let line = format!("Playing as {}", chosen.0);
+    if text.0 != line {
+        text.0 = line;
+    }
+}
@@ -0,0 +1,23 @@
+use bevy::prelude::*;
+use crate::net::client::LastDisconnect;
+use super::{AppState, MenuAction};
+
+pub fn spawn_menu(mut commands: Commands, mut last: ResMut<LastDisconnect>) {
+    let reason = last.0.take();
+    commands.spawn(super::ui_camera(AppState::Menu));
+    commands.spawn(super::screen(AppState::Menu)).with_children(|p| {
+        p.spawn(super::title("Isometric"));
+        if let Some(reason) = reason {
+            p.spawn(super::label(reason));
+        }
+        p.spawn(super::button("Play", MenuAction::Goto(AppState::Lobby)));
+        p.spawn(super::button("Quit", MenuAction::Quit));
+    });
+}
+
+/// Enter = Play.
+pub fn menu_keys(keys: Res<ButtonInput<KeyCode>>, mut next: ResMut<NextState<AppState>>) {
+    if keys.just_pressed(KeyCode::Enter) {
+        next.set(AppState::Lobby);
+    }
+}
@@ -0,0 +1,93 @@
+use bevy::{prelude::*, app::AppExit};
+
+pub mod menu;
+pub mod lobby;
+pub mod game;
+
+/// Top-level flow: Menu -> Lobby -> Loading -> InGame. Esc steps back.
+#[derive(States, Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AppState {
+    #[default]
+    Menu,
+    Lobby,
+    Loading,
+    InGame,
+}
+
+/// What a `MenuButton` does when clicked.
+#[derive(Clone, Copy, Debug)]
+pub enum MenuAction {
+    Goto(AppState),
+    Quit,
+}
+
+#[derive(Component)]
+pub struct MenuButton(pub MenuAction);
+
+const BUTTON_IDLE: Color = Color::srgb(0.2, 0.2, 0.25);
+const BUTTON_HOVER: Color = Color::srgb(0.3, 0.3, 0.4);
+
+/* ---------------- UI building blocks ---------------- */
+
+/// Full-window, centred column that despawns when `state` is left.
+pub fn screen(state: AppState) -> impl Bundle {
+    (
+        DespawnOnExit(state),
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            row_gap: Val::Px(12.0),
+            ..default()
+        },
+    )
+}
+
+pub fn title(text: impl Into<String>) -> impl Bundle {
+    (Text::new(text), TextFont { font_size: 48.0, ..default() })
+}
+
+pub fn label(text: impl Into<String>) -> impl Bundle {
+    (Text::new(text), TextFont { font_size: 20.0, ..default() })
+}
+
+pub fn button(text: impl Into<String>, action: MenuAction) -> impl Bundle {
+    (
+        Button,
+        MenuButton(action),
+        Node {
+            width: Val::Px(220.0),
+            padding: UiRect::all(Val::Px(10.0)),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        BackgroundColor(BUTTON_IDLE),
+        children![(Text::new(text), TextFont { font_size: 24.0, ..default() })],
+    )
+}
+
+/// Menu screens have no 3D camera, so each spawns its own UI camera.
+pub fn ui_camera(state: AppState) -> impl Bundle {
+    (Camera2d, DespawnOnExit(state))
+}
+
+/* ---------------- Systems ---------------- */
+
+pub fn handle_menu_buttons(
+    mut q: Query<(&Interaction, &MenuButton, &mut BackgroundColor), Changed<Interaction>>,
+    mut next: ResMut<NextState<AppState>>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    for (interaction, button, mut bg) in &mut q {
+        match interaction {
+            Interaction::Pressed => match button.0 {
+                MenuAction::Goto(state) => next.set(state),
+                MenuAction::Quit => { exit.write(AppExit::Success); }
+            },
+            Interaction::Hovered => bg.0 = BUTTON_HOVER,
+            Interaction::None => bg.0 = BUTTON_IDLE,
+        }
+    }
+}
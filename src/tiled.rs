@@ -0,0 +1,648 @@
+use bevy::{
+    prelude::*,
+    asset::{io::Reader, AssetLoader, LoadContext},
+};
+use serde::{Deserialize, Serialize};
+use crate::level::{Level, Prop, SpawnPoint, TileKind};
+use crate::world;
+
+/// Tiled stores flip/rotation flags in the top bits of every gid.
+const GID_MASK: u32 = 0x1FFF_FFFF;
+
+/// Per-file settings (can be overridden in a `.meta` file next to the map).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TiledSettings {
+    /// Tile layer whose non-empty cells become `world::Blocked` walls.
+    pub collision_layer: String,
+}
+
+impl Default for TiledSettings {
+    fn default() -> Self {
+        Self { collision_layer: "collision".to_string() }
+    }
+}
+
+/* ---------------- Format-neutral map ---------------- */
+
+/// The subset of a Tiled map we understand, parsed from either `.tmj` or `.tmx`.
+struct TiledMap {
+    orientation: String,
+    infinite: bool,
+    width: usize,
+    height: usize,
+    tile_w: f32,
+    tile_h: f32,
+    layers: Vec<TiledLayer>, // group layers already flattened
+}
+
+enum TiledLayer {
+    Tiles { name: String, gids: Vec<u32> },
+    Objects { objects: Vec<TiledObject> },
+}
+
+struct TiledObject {
+    name: String,
+    class: String, // `class` in Tiled 1.9+, `type` before that
+    x: f32,        // pixels
+    y: f32,
+    rotation: f32, // degrees, clockwise
+    model: Option<String>,
+}
+
+impl TiledMap {
+    /// Turns the map into a [`Level`]:
+    /// - the collision layer's non-empty cells become walls
+    /// - objects of class `spawn` become spawn points (named by the object name)
+    /// - objects of class `prop` (with a `model` string property) become props
+    ///
+    /// Tiled's tile `i` spans `i..i + 1` in object coordinates, while our cell
+    /// `i` is centred on `i` (see `world::cell_at`): objects move back half a tile.
+    fn into_level(self, path: &str, settings: &TiledSettings) -> Result<Level, TiledLoadError> {
+        let unsupported = |feature: String| TiledLoadError::Unsupported { path: path.to_string(), feature };
+        match self.orientation.as_str() {
+            "orthogonal" | "isometric" => {}
+            "hexagonal" => return Err(unsupported("hexagonal maps".into())),
+            "staggered" => return Err(unsupported("staggered isometric maps".into())),
+            other => return Err(unsupported(format!("{other:?} orientations"))),
+        }
+        if self.infinite {
+            return Err(unsupported("infinite maps (disable \"Infinite\" in Map Properties)".into()));
+        }
+
+        // Both orthogonal and isometric maps index tiles on the same square grid;
+        // isometric maps measure object positions in `tile_h` units along both axes.
+        let (unit_x, unit_y) = if self.orientation == "isometric" {
+            (self.tile_h, self.tile_h)
+        } else {
+            (self.tile_w, self.tile_h)
+        };
+
+        let mut tiles = vec![TileKind::Floor; self.width * self.height];
+        let mut spawns = Vec::new();
+        let mut props = Vec::new();
+        let mut found_collision = false;
+
+        for layer in self.layers {
+            match layer {
+                TiledLayer::Tiles { name, gids } => {
+                    if gids.len() != self.width * self.height {
+                        return Err(TiledLoadError::LayerSize {
+                            path: path.to_string(),
+                            layer: name,
+                            expected: self.width * self.height,
+                            found: gids.len(),
+                        });
+                    }
+                    if name != settings.collision_layer { continue; }
+                    found_collision = true;
+                    for (i, gid) in gids.into_iter().enumerate() {
+                        if gid & GID_MASK != 0 {
+                            tiles[i] = TileKind::Wall;
+                        }
+                    }
+                }
+                TiledLayer::Objects { objects } => {
+                    for obj in objects {
+                        let gx = obj.x / unit_x - 0.5;
+                        let gy = obj.y / unit_y - 0.5;
+                        match obj.class.as_str() {
+                            "spawn" => {
+                                let (x, y) = world::cell_at(gx, gy);
+                                if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+                                    return Err(TiledLoadError::ObjectOutOfBounds {
+                                        path: path.to_string(),
+                                        name: obj.name,
+                                        x,
+                                        y,
+                                    });
+                                }
+                                spawns.push(SpawnPoint { name: obj.name, x, y });
+                            }
+                            "prop" => {
+                                let Some(model) = obj.model else {
+                                    return Err(TiledLoadError::MissingModel { path: path.to_string(), name: obj.name });
+                                };
+                                props.push(Prop { model, x: gx, y: gy, rotation_deg: -obj.rotation });
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+
+        if !found_collision {
+            warn!("{path}: no tile layer named {:?}, map has no walls", settings.collision_layer);
+        }
+
+        Ok(Level { width: self.width, height: self.height, tiles, spawns, props })
+    }
+}
+
+/* ---------------- .tmj (JSON) ---------------- */
+
+#[derive(Deserialize)]
+struct TmjMap {
+    orientation: String,
+    #[serde(default)]
+    infinite: bool,
+    width: usize,
+    height: usize,
+    tilewidth: f32,
+    tileheight: f32,
+    #[serde(default)]
+    layers: Vec<TmjLayer>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum TmjLayer {
+    TileLayer {
+        name: String,
+        data: Option<TmjData>,
+        #[serde(default)]
+        encoding: Option<String>,
+        #[serde(default)]
+        compression: Option<String>,
+    },
+    ObjectGroup {
+        #[serde(default)]
+        objects: Vec<TmjObject>,
+    },
+    ImageLayer {},
+    Group {
+        #[serde(default)]
+        layers: Vec<TmjLayer>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TmjData {
+    Csv(Vec<serde_json::Value>), // checked to be gids by `parse_tmj`
+    Encoded(serde::de::IgnoredAny), // base64, possibly compressed
+}
+
+#[derive(Deserialize)]
+struct TmjObject {
+    #[serde(default)]
+    name: String,
+    #[serde(default, alias = "type")]
+    class: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    rotation: f32,
+    #[serde(default)]
+    properties: Vec<TmjProperty>,
+}
+
+#[derive(Deserialize)]
+struct TmjProperty {
+    name: String,
+    value: serde_json::Value,
+}
+
+fn parse_tmj(path: &str, bytes: &[u8]) -> Result<TiledMap, TiledLoadError> {
+    let map: TmjMap = serde_json::from_slice(bytes).map_err(|e| TiledLoadError::Syntax {
+        path: path.to_string(),
+        line: e.line(),
+        col: e.column(),
+        msg: e.to_string(),
+    })?;
+
+    fn flatten(path: &str, layers: Vec<TmjLayer>, out: &mut Vec<TiledLayer>) -> Result<(), TiledLoadError> {
+        for layer in layers {
+            match layer {
+                TmjLayer::TileLayer { name, data, encoding, compression } => {
+                    let gids = match data {
+                        Some(TmjData::Csv(values)) => values
+                            .iter()
+                            .enumerate()
+                            .map(|(index, v)| {
+                                v.as_u64().and_then(|g| u32::try_from(g).ok()).ok_or_else(|| TiledLoadError::BadTile {
+                                    path: path.to_string(),
+                                    layer: name.clone(),
+                                    index,
+                                    value: v.to_string(),
+                                })
+                            })
+                            .collect::<Result<_, _>>()?,
+                        _ => {
+                            let encoding = match (encoding, compression) {
+                                (Some(e), Some(c)) if !c.is_empty() => format!("{e}+{c}"),
+                                (Some(e), _) => e,
+                                (None, _) => "chunked".to_string(),
+                            };
+                            return Err(TiledLoadError::Encoding { path: path.to_string(), layer: name, encoding });
+                        }
+                    };
+                    out.push(TiledLayer::Tiles { name, gids });
+                }
+                TmjLayer::ObjectGroup { objects } => {
+                    let objects = objects
+                        .into_iter()
+                        .map(|o| TiledObject {
+                            model: o
+                                .properties
+                                .iter()
+                                .find(|p| p.name == "model")
+                                .and_then(|p| p.value.as_str())
+                                .map(str::to_string),
+                            name: o.name,
+                            class: o.class,
+                            x: o.x,
+                            y: o.y,
+                            rotation: o.rotation,
+                        })
+                        .collect();
+                    out.push(TiledLayer::Objects { objects });
+                }
+                TmjLayer::ImageLayer {} => {}
+                TmjLayer::Group { layers } => flatten(path, layers, out)?,
+            }
+        }
+        Ok(())
+    }
+
+    let mut layers = Vec::new();
+    flatten(path, map.layers, &mut layers)?;
+
+    Ok(TiledMap {
+        orientation: map.orientation,
+        infinite: map.infinite,
+        width: map.width,
+        height: map.height,
+        tile_w: map.tilewidth,
+        tile_h: map.tileheight,
+        layers,
+    })
+}
+
+/* ---------------- .tmx (XML) ---------------- */
+
+fn parse_tmx(path: &str, bytes: &[u8]) -> Result<TiledMap, TiledLoadError> {
+    let syntax = |line: usize, col: usize, msg: String| TiledLoadError::Syntax { path: path.to_string(), line, col, msg };
+
+    let text = std::str::from_utf8(bytes).map_err(|e| syntax(0, 0, e.to_string()))?;
+    let doc = roxmltree::Document::parse(text).map_err(|e| {
+        let pos = e.pos();
+        syntax(pos.row as usize, pos.col as usize, e.to_string())
+    })?;
+
+    // Reports a bad/missing attribute at the element's position in the file.
+    let attr = |node: roxmltree::Node, name: &str| -> Result<String, TiledLoadError> {
+        node.attribute(name).map(str::to_string).ok_or_else(|| {
+            let pos = doc.text_pos_at(node.range().start);
+            syntax(pos.row as usize, pos.col as usize, format!("<{}> is missing `{name}`", node.tag_name().name()))
+        })
+    };
+    let num = |node: roxmltree::Node, name: &str| -> Result<f32, TiledLoadError> {
+        attr(node, name)?.parse::<f32>().map_err(|e| {
+            let pos = doc.text_pos_at(node.range().start);
+            syntax(pos.row as usize, pos.col as usize, format!("`{name}`: {e}"))
+        })
+    };
+
+    let map = doc.root_element();
+    if map.tag_name().name() != "map" {
+        return Err(syntax(1, 1, "root element is not <map>".into()));
+    }
+
+    fn flatten(
+        path: &str,
+        parent: roxmltree::Node,
+        num: &dyn Fn(roxmltree::Node, &str) -> Result<f32, TiledLoadError>,
+        out: &mut Vec<TiledLayer>,
+    ) -> Result<(), TiledLoadError> {
+        for node in parent.children().filter(|n| n.is_element()) {
+            match node.tag_name().name() {
+                "layer" => {
+                    let name = node.attribute("name").unwrap_or_default().to_string();
+                    let Some(data) = node.children().find(|n| n.has_tag_name("data")) else {
+                        out.push(TiledLayer::Tiles { name, gids: Vec::new() });
+                        continue;
+                    };
+                    let gid = |index: usize, value: &str| {
+                        value.parse::<u32>().map_err(|_| TiledLoadError::BadTile {
+                            path: path.to_string(),
+                            layer: name.clone(),
+                            index,
+                            value: value.to_string(),
+                        })
+                    };
+                    let gids = match (data.attribute("encoding"), data.attribute("compression")) {
+                        (Some("csv"), None) => data
+                            .text()
+                            .unwrap_or_default()
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|s| !s.is_empty())
+                            .enumerate()
+                            .map(|(i, s)| gid(i, s))
+                            .collect::<Result<_, _>>()?,
+                        (None, None) if data.children().any(|n| n.has_tag_name("chunk")) => {
+                            return Err(TiledLoadError::Encoding { path: path.to_string(), layer: name, encoding: "chunked".into() });
+                        }
+                        // Tiled leaves `gid` out of empty tiles.
+                        (None, None) => data
+                            .children()
+                            .filter(|n| n.has_tag_name("tile"))
+                            .enumerate()
+                            .map(|(i, t)| t.attribute("gid").map_or(Ok(0), |g| gid(i, g)))
+                            .collect::<Result<_, _>>()?,
+                        (e, c) => {
+                            let encoding = [e, c].into_iter().flatten().collect::<Vec<_>>().join("+");
+                            return Err(TiledLoadError::Encoding { path: path.to_string(), layer: name, encoding });
+                        }
+                    };
+                    out.push(TiledLayer::Tiles { name, gids });
+                }
+                "objectgroup" => {
+                    let mut objects = Vec::new();
+                    for obj in node.children().filter(|n| n.has_tag_name("object")) {
+                        let model = obj
+                            .children()
+                            .filter(|n| n.has_tag_name("properties"))
+                            .flat_map(|p| p.children().filter(|n| n.has_tag_name("property")))
+                            .find(|p| p.attribute("name") == Some("model"))
+                            .and_then(|p| p.attribute("value").map(str::to_string).or_else(|| p.text().map(str::to_string)));
+                        objects.push(TiledObject {
+                            name: obj.attribute("name").unwrap_or_default().to_string(),
+                            class: obj.attribute("class").or(obj.attribute("type")).unwrap_or_default().to_string(),
+                            x: num(obj, "x")?,
+                            y: num(obj, "y")?,
+                            rotation: obj.attribute("rotation").and_then(|r| r.parse().ok()).unwrap_or(0.0),
+                            model,
+                        });
+                    }
+                    out.push(TiledLayer::Objects { objects });
+                }
+                "group" => flatten(path, node, num, out)?,
+                _ => {} // tilesets, image layers, properties
+            }
+        }
+        Ok(())
+    }
+
+    let mut layers = Vec::new();
+    flatten(path, map, &num, &mut layers)?;
+
+    Ok(TiledMap {
+        orientation: attr(map, "orientation")?,
+        infinite: map.attribute("infinite") == Some("1"),
+        width: num(map, "width")? as usize,
+        height: num(map, "height")? as usize,
+        tile_w: num(map, "tilewidth")?,
+        tile_h: num(map, "tileheight")?,
+        layers,
+    })
+}
+
+/* ---------------- Errors ---------------- */
+
+#[derive(Debug)]
+pub enum TiledLoadError {
+    Io { path: String, source: std::io::Error },
+    Syntax { path: String, line: usize, col: usize, msg: String },
+    Unsupported { path: String, feature: String },
+    Encoding { path: String, layer: String, encoding: String },
+    LayerSize { path: String, layer: String, expected: usize, found: usize },
+    BadTile { path: String, layer: String, index: usize, value: String },
+    ObjectOutOfBounds { path: String, name: String, x: i32, y: i32 },
+    MissingModel { path: String, name: String },
+}
+
+impl std::fmt::Display for TiledLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TiledLoadError::Io { path, source } => write!(f, "{path}: could not read map: {source}"),
+            TiledLoadError::Syntax { path, line, col, msg } => write!(f, "{path}:{line}:{col}: {msg}"),
+            TiledLoadError::Unsupported { path, feature } => write!(f, "{path}: {feature} are not supported"),
+            TiledLoadError::Encoding { path, layer, encoding } => write!(
+                f,
+                "{path}: layer {layer:?} uses {encoding:?} tile data; set the layer format to CSV in Tiled"
+            ),
+            TiledLoadError::LayerSize { path, layer, expected, found } => {
+                write!(f, "{path}: layer {layer:?} has {found} tiles, expected {expected}")
+            }
+            TiledLoadError::BadTile { path, layer, index, value } => {
+                write!(f, "{path}: layer {layer:?} tile {index} is {value}, not a tile id")
+            }
+            TiledLoadError::ObjectOutOfBounds { path, name, x, y } => {
+                write!(f, "{path}: spawn object {name:?} at tile ({x}, {y}) is outside the map")
+            }
+            TiledLoadError::MissingModel { path, name } => {
+                write!(f, "{path}: prop object {name:?} has no `model` property")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TiledLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TiledLoadError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/* ---------------- Asset loader ---------------- */
+
+/// Loads Tiled maps (`.tmj` / `.tmx`) as a [`Level`], so they spawn exactly
+/// like `.level.ron` files.
+#[derive(Default)]
+pub struct TiledLoader;
+
+impl AssetLoader for TiledLoader {
+    type Asset = Level;
+    type Settings = TiledSettings;
+    type Error = TiledLoadError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        settings: &TiledSettings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Level, TiledLoadError> {
+        let path = load_context.path().display().to_string();
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(|source| TiledLoadError::Io { path: path.clone(), source })?;
+
+        let map = match load_context.path().extension().and_then(|e| e.to_str()) {
+            Some("tmx") => parse_tmx(&path, &bytes)?,
+            _ => parse_tmj(&path, &bytes)?,
+        };
+        map.into_level(&path, settings)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmj", "tmx"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// `assets/levels/sample.tmj` saved as `.tmx` (CSV layer format).
+    const SAMPLE_TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" tiledversion="1.10.2" orientation="isometric" renderorder="right-down" width="6" height="6" tilewidth="64" tileheight="32" infinite="0">
+ <tileset firstgid="1" source="tiles.tsx"/>
+ <layer id="1" name="ground" width="6" height="6">
+  <data encoding="csv">
+1,1,1,1,1,1,
+1,1,1,1,1,1,
+1,1,1,1,1,1,
+1,1,1,1,1,1,
+1,1,1,1,1,1,
+1,1,1,1,1,1
+</data>
+ </layer>
+ <group id="4" name="level">
+  <layer id="2" name="collision" width="6" height="6">
+   <data encoding="csv">
+2,2,2,2,2,2,
+2,0,0,0,0,2,
+2,0,0,0,0,2,
+2,0,0,2,0,2,
+2,0,0,0,0,2,
+2,2,2,2,2,2
+</data>
+  </layer>
+ </group>
+ <objectgroup id="3" name="objects">
+  <object id="1" name="player" type="spawn" x="48" y="48"><point/></object>
+  <object id="2" name="crate" class="prop" x="112" y="80">
+   <properties>
+    <property name="model" value="models/resource.glb#Scene0"/>
+   </properties>
+   <point/>
+  </object>
+ </objectgroup>
+</map>
+"#;
+
+    fn load(path: &str, bytes: &[u8]) -> Result<Level, TiledLoadError> {
+        let map = if path.ends_with(".tmx") { parse_tmx(path, bytes)? } else { parse_tmj(path, bytes)? };
+        map.into_level(path, &TiledSettings::default())
+    }
+
+    fn assert_is_sample(level: &Level) {
+        assert_eq!((level.width, level.height), (6, 6));
+        let walls: Vec<_> = level.blocked_cells().collect();
+        assert_eq!(walls.len(), 6 + 6 + 4 + 4 + 1);
+        assert!(walls.contains(&(3, 3)));
+        assert!(!walls.contains(&(1, 1)));
+
+        let spawns: Vec<_> = level.spawns.iter().map(|s| (s.name.as_str(), s.x, s.y)).collect();
+        assert_eq!(spawns, [("player", 1, 1)]);
+        // Isometric maps measure objects in `tileheight` units along both axes.
+        let props: Vec<_> = level.props.iter().map(|p| (p.model.as_str(), p.x, p.y)).collect();
+        // (3.5, 2.5) tiles in, the middle of tile (3, 2).
+        assert_eq!(props, [("models/resource.glb#Scene0", 3.0, 2.0)]);
+    }
+
+    /// A 2x1 map with a single collision layer.
+    fn tmj(map: serde_json::Value, layer: serde_json::Value) -> Vec<u8> {
+        let mut base = json!({
+            "orientation": "orthogonal", "width": 2, "height": 1, "tilewidth": 32, "tileheight": 32,
+            "layers": [layer],
+        });
+        base.as_object_mut().unwrap().extend(map.as_object().unwrap().clone());
+        serde_json::to_vec(&base).unwrap()
+    }
+
+    fn csv_layer() -> serde_json::Value {
+        json!({ "type": "tilelayer", "name": "collision", "data": [0, 1] })
+    }
+
+    #[test]
+    fn the_sample_map_loads() {
+        let level = load("levels/sample.tmj", include_bytes!("../assets/levels/sample.tmj")).unwrap();
+        assert_is_sample(&level);
+    }
+
+    #[test]
+    fn tmx_maps_load_like_tmj() {
+        let level = load("levels/sample.tmx", SAMPLE_TMX.as_bytes()).unwrap();
+        assert_is_sample(&level);
+    }
+
+    #[test]
+    fn unsupported_maps_are_rejected() {
+        let cases = [
+            (json!({ "orientation": "hexagonal" }), "hexagonal maps"),
+            (json!({ "orientation": "staggered" }), "staggered isometric maps"),
+            (json!({ "infinite": true }), "infinite maps"),
+        ];
+        for (map, feature) in cases {
+            let err = load("m.tmj", &tmj(map, csv_layer())).unwrap_err();
+            assert!(matches!(&err, TiledLoadError::Unsupported { .. }), "{err:?}");
+            assert!(err.to_string().starts_with(&format!("m.tmj: {feature}")), "{err}");
+            assert!(err.to_string().contains("are not supported"), "{err}");
+        }
+
+        let hex = SAMPLE_TMX.replace("orientation=\"isometric\"", "orientation=\"hexagonal\"");
+        let err = load("m.tmx", hex.as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "m.tmx: hexagonal maps are not supported");
+        let infinite = SAMPLE_TMX.replace("infinite=\"0\"", "infinite=\"1\"");
+        assert!(matches!(load("m.tmx", infinite.as_bytes()), Err(TiledLoadError::Unsupported { .. })));
+    }
+
+    #[test]
+    fn encoded_layers_are_rejected() {
+        let base64 = json!({ "type": "tilelayer", "name": "collision", "data": "AAAAAAEAAAA=", "encoding": "base64" });
+        let zlib = json!({ "type": "tilelayer", "name": "collision", "data": "eJxjYGBgAAAABAAB", "encoding": "base64", "compression": "zlib" });
+        let chunked = json!({ "type": "tilelayer", "name": "collision", "chunks": [] });
+        for (layer, encoding) in [(base64, "base64"), (zlib, "base64+zlib"), (chunked, "chunked")] {
+            let err = load("m.tmj", &tmj(json!({}), layer)).unwrap_err();
+            assert!(matches!(&err, TiledLoadError::Encoding { layer, .. } if layer == "collision"), "{err:?}");
+            assert_eq!(
+                err.to_string(),
+                format!("m.tmj: layer \"collision\" uses {encoding:?} tile data; set the layer format to CSV in Tiled"),
+            );
+        }
+
+        let compressed = SAMPLE_TMX.replacen("<data encoding=\"csv\">", "<data encoding=\"base64\" compression=\"zstd\">", 1);
+        let err = load("m.tmx", compressed.as_bytes()).unwrap_err();
+        assert!(matches!(&err, TiledLoadError::Encoding { encoding, .. } if encoding == "base64+zstd"), "{err:?}");
+    }
+
+    #[test]
+    fn bad_tile_ids_name_the_layer_and_tile() {
+        let layer = json!({ "type": "tilelayer", "name": "collision", "data": [0, "wall"] });
+        let err = load("m.tmj", &tmj(json!({}), layer)).unwrap_err();
+        assert_eq!(err.to_string(), "m.tmj: layer \"collision\" tile 1 is \"wall\", not a tile id");
+        let layer = json!({ "type": "tilelayer", "name": "collision", "data": [-1, 0] });
+        let err = load("m.tmj", &tmj(json!({}), layer)).unwrap_err();
+        assert!(matches!(&err, TiledLoadError::BadTile { index: 0, .. }), "{err:?}");
+
+        let csv = SAMPLE_TMX.replacen("2,0,0,2,0,2", "2,0,0,x,0,2", 1);
+        let err = load("m.tmx", csv.as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "m.tmx: layer \"collision\" tile 21 is x, not a tile id");
+
+        let xml = |gid: &str| {
+            let tiles = format!("<tile/><tile gid=\"{gid}\"/>");
+            let layer = format!("<layer id=\"1\" name=\"collision\" width=\"2\" height=\"1\"><data>{tiles}</data></layer>");
+            format!("<map orientation=\"orthogonal\" width=\"2\" height=\"1\" tilewidth=\"32\" tileheight=\"32\">{layer}</map>")
+        };
+        let level = load("m.tmx", xml("1").as_bytes()).unwrap();
+        assert_eq!(level.blocked_cells().collect::<Vec<_>>(), [(1, 0)], "a tile without a gid is empty");
+        let err = load("m.tmx", xml("1.5").as_bytes()).unwrap_err();
+        assert!(matches!(&err, TiledLoadError::BadTile { layer, index: 1, .. } if layer == "collision"), "{err:?}");
+    }
+
+    #[test]
+    fn syntax_errors_name_the_position() {
+        let err = load("m.tmj", b"{\n  \"width\": }").unwrap_err();
+        assert!(matches!(err, TiledLoadError::Syntax { line: 2, .. }), "{err:?}");
+        let err = load("m.tmx", b"<map>\n<layer></map>").unwrap_err();
+        assert!(matches!(err, TiledLoadError::Syntax { line: 2, .. }), "{err:?}");
+    }
+}
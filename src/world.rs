@@ -1,9 +1,23 @@
 use bevy::{prelude::*};
-use crate::constants;
 
 #[derive(Component)]
 pub struct FollowLight; // tag your light
 
+/// The player this client controls (as opposed to remote players or NPCs).
+#[derive(Component, Debug)]
+pub struct LocalPlayer;
+
+/// Players on the same team share what they have explored (see `fog`).
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Team(pub u8);
+
+impl Team {
+    /// `--team N`; players without one share with nobody.
+    pub fn from_args() -> Option<Self> {
+        crate::net::arg_value("--team")?.parse().ok().map(Team)
+    }
+}
+
 /// Mark things that block movement on the grid (tiles, walls, crates).
 #[derive(Component, Debug)]
 pub struct Solid;
@@ -12,15 +26,30 @@ pub struct Solid;
 #[derive(Resource, Default, Debug)]
 pub struct Blocked(pub std::collections::HashSet<(i32, i32)>);
 
+impl Blocked {
+    pub fn is_blocked(&self, cell: (i32, i32)) -> bool {
+        self.0.contains(&cell)
+    }
+
+    /// True if a square footprint of half-size `half` centred on the fractional
+    /// grid position (x, y) touches any blocked cell.
+    /// Cell (cx, cy) covers [cx - 0.5, cx + 0.5) on each axis (see [`cell_at`]).
+    pub fn overlaps(&self, x: f32, y: f32, half: f32) -> bool {
+        let (x0, y0) = cell_at(x - half, y - half);
+        let (x1, y1) = cell_at(x + half, y + half);
+        (x0..=x1).any(|cx| (y0..=y1).any(|cy| self.0.contains(&(cx, cy))))
+    }
+}
+
 #[derive(Component, Clone, Copy, Debug, PartialEq)]
 pub struct GridPos { pub x: f32, pub y: f32 }
 // pub struct GridPos { pub x: i32, pub y: i32 }
 
-/// Grid (gx, gy) -> world (x, z) for rendering (Y is height).
-pub fn iso_world_from_grid(gx: i32, gy: i32, tile_w: f32, tile_h: f32) -> Vec3 {
-    let x = (gx as f32 - gy as f32) * (tile_w * 0.5);
-    let z = (gx as f32 + gy as f32) * (tile_h * 0.5);
-    Vec3::new(x, 0.5, z)    
+/// The cell a fractional `GridPos` is standing in.
+/// Integer positions are cell centres (that is where `grid_to_iso` draws them),
+/// so this rounds rather than truncates.
+pub fn cell_at(x: f32, y: f32) -> (i32, i32) {
+    ((x + 0.5).floor() as i32, (y + 0.5).floor() as i32)
 }
 
 /// Grid (gx, gy) -> world (x, z) for rendering (Y is height).
@@ -30,27 +59,51 @@ pub fn grid_to_iso(gx: f32, gy: f32, _tile_w: f32, _tile_h: f32) -> Vec3 {
     Vec3::new(x, 0.5, z)    
 }
 
-/// Inverse: world (x, z) -> fractional grid (gx, gy).
-/// Useful if you ever need to pick tiles with a cursor or do continuous motion.
-/// Round to i32 as needed.
-pub fn grid_from_iso_world(x: f32, z: f32, tile_w: f32, tile_h: f32) -> Vec2 {
-    let a = x / (tile_w * 0.5);
-    let b = z / (tile_h * 0.5);
-    // Solve:
-    // a = gx - gy
-    // b = gx + gy
-    // => gx = (a + b)/2, gy = (b - a)/2
-    Vec2::new((a + b) * 0.5, (b - a) * 0.5)
+/// Inverse of `grid_to_iso`: world (x, z) -> fractional grid (gx, gy).
+/// Useful for picking tiles with a cursor; use `cell_at` to get the tile.
+pub fn grid_from_iso_world(x: f32, z: f32, _tile_w: f32, _tile_h: f32) -> Vec2 {
+    Vec2::new(x - 0.5, -z - 0.5)
 }
 
 pub fn light_player(        
-    player_q: Query<(&GridPos), Without<Solid>>,
+    player_q: Query<&GridPos, With<LocalPlayer>>,
     mut light_q: Query<&mut Transform, With<FollowLight>>,
 ) {            
-    
-    let Ok(mut player) = player_q.single() else { panic!() };    
-    let Ok((mut light_tf)) = light_q.single_mut() else { panic!() };
-    // let target = grid_to_iso(player.x, player.y, constants::TILE_W, constants::TILE_H);
-    light_tf.translation.x = player.x;
-    light_tf.translation.z = -player.y;  
-}
\ No newline at end of file
+    let Ok(player) = player_q.single() else { return };
+    for mut light_tf in &mut light_q {
+        light_tf.translation.x = player.x;
+        light_tf.translation.z = -player.y;  
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{TILE_H, TILE_W};
+
+    #[test]
+    fn cells_are_centred_on_whole_positions() {
+        assert_eq!(cell_at(0.0, 0.0), (0, 0));
+        assert_eq!(cell_at(0.49, -0.49), (0, 0));
+        // Each cell covers [c - 0.5, c + 0.5): the upper edge belongs to the next one.
+        assert_eq!(cell_at(0.5, -0.5), (1, 0));
+        assert_eq!(cell_at(-0.51, 1.5), (-1, 2));
+        assert_eq!(cell_at(2.999, -3.0), (3, -3));
+    }
+
+    #[test]
+    fn footprints_touch_the_cells_they_reach() {
+        let blocked = Blocked([(1, 0)].into_iter().collect());
+        assert!(!blocked.overlaps(0.0, 0.0, 0.4));
+        assert!(blocked.overlaps(0.1, 0.0, 0.4), "reaching x = 0.5 touches the wall");
+        assert!(!blocked.overlaps(1.0, 0.95, 0.4), "the row above is clear");
+        assert!(blocked.overlaps(1.0, 0.85, 0.4));
+    }
+
+    #[test]
+    fn picking_undoes_rendering() {
+        for g in [Vec2::ZERO, Vec2::new(3.0, -2.0), Vec2::new(-7.25, 11.5), Vec2::new(0.49, 0.51)] {
+            let world = grid_to_iso(g.x, g.y, TILE_W, TILE_H);
+            assert_eq!(grid_from_iso_world(world.x, world.z, TILE_W, TILE_H), g);
+        }
+    }
+}